@@ -0,0 +1,256 @@
+use actix_web::{dev::ServerHandle, get, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::spotify::{SpotifyClient, SpotifyConfig};
+
+const TOKEN: &str = include_str!("../../tests/fixtures/spotify/token.json");
+const TRACKS: &str = include_str!("../../tests/fixtures/spotify/tracks.json");
+
+// Faux serveur Spotify servant les fixtures, pour tester l'analyse sans réseau
+pub struct MockSpotify {
+    state: web::Data<MockState>,
+    base_url: String,
+    handle: ServerHandle,
+}
+
+struct MockState {
+    tracks: Vec<Value>,
+    token_requests: AtomicUsize,
+    token_lifetime: AtomicU64,
+    rate_limited: AtomicUsize,
+    image_downloads: AtomicUsize,
+    searches: Mutex<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct MockSearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+impl MockSpotify {
+    pub async fn start() -> MockSpotify {
+        let state = web::Data::new(MockState {
+            tracks: serde_json::from_str(TRACKS).expect("invalid tracks fixture"),
+            token_requests: AtomicUsize::new(0),
+            token_lifetime: AtomicU64::new(0),
+            rate_limited: AtomicUsize::new(0),
+            image_downloads: AtomicUsize::new(0),
+            searches: Mutex::new(Vec::new()),
+        });
+
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .service(get_token)
+                .service(search_tracks)
+                .service(get_track)
+                .service(get_image)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("unable to bind mock Spotify server");
+
+        let base_url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        MockSpotify {
+            state,
+            base_url,
+            handle,
+        }
+    }
+
+    pub fn config(&self) -> SpotifyConfig {
+        SpotifyConfig {
+            api_url: format!("{}/v1/", self.base_url),
+            token_url: format!("{}/api/token", self.base_url),
+            client_id: "mock-id".to_string(),
+            client_secret: "mock-secret".to_string(),
+            requests_per_second: 1000.0,
+        }
+    }
+
+    pub fn client(&self) -> SpotifyClient {
+        SpotifyClient::new(self.config())
+    }
+
+    pub fn token_requests(&self) -> usize {
+        self.state.token_requests.load(Ordering::SeqCst)
+    }
+
+    pub fn searches(&self) -> Vec<String> {
+        self.state.searches.lock().unwrap().clone()
+    }
+
+    pub fn image_downloads(&self) -> usize {
+        self.state.image_downloads.load(Ordering::SeqCst)
+    }
+
+    // Durée de validité annoncée pour les prochains jetons, à la place de celle de la fixture
+    pub fn token_lifetime(&self, seconds: u64) {
+        self.state.token_lifetime.store(seconds, Ordering::SeqCst);
+    }
+
+    // Les `count` prochaines recherches répondent 429 avec Retry-After
+    pub fn rate_limit(&self, count: usize) {
+        self.state.rate_limited.store(count, Ordering::SeqCst);
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
+
+#[post("/api/token")]
+async fn get_token(state: web::Data<MockState>, req: HttpRequest) -> HttpResponse {
+    let authorized = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Basic "));
+
+    if !authorized {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_client" }));
+    }
+
+    state.token_requests.fetch_add(1, Ordering::SeqCst);
+    let mut token: Value = serde_json::from_str(TOKEN).expect("invalid token fixture");
+    let lifetime = state.token_lifetime.load(Ordering::SeqCst);
+    if lifetime > 0 {
+        token["expires_in"] = json!(lifetime);
+    }
+    HttpResponse::Ok().json(token)
+}
+
+#[get("/v1/search")]
+async fn search_tracks(
+    state: web::Data<MockState>,
+    req: HttpRequest,
+    query: web::Query<MockSearchQuery>,
+) -> HttpResponse {
+    if let Some(response) = check_bearer(&req) {
+        return response;
+    }
+
+    state.searches.lock().unwrap().push(query.q.clone());
+
+    let limited = state
+        .rate_limited
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+            count.checked_sub(1)
+        });
+    if limited.is_ok() {
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", "1"))
+            .finish();
+    }
+
+    let limit = query.limit.unwrap_or(20);
+    let items: Vec<&Value> = state
+        .tracks
+        .iter()
+        .filter(|track| matches(track, &query.q))
+        .take(limit)
+        .collect();
+
+    with_local_images(
+        &req,
+        json!({
+            "tracks": {
+                "href": format!("https://api.spotify.com/v1/search?q={}", query.q),
+                "limit": limit,
+                "next": null,
+                "offset": 0,
+                "previous": null,
+                "total": items.len(),
+                "items": items,
+            }
+        }),
+    )
+}
+
+#[get("/v1/tracks/{id}")]
+async fn get_track(
+    state: web::Data<MockState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> HttpResponse {
+    if let Some(response) = check_bearer(&req) {
+        return response;
+    }
+
+    match state.tracks.iter().find(|track| track["id"] == id.as_str()) {
+        Some(track) => with_local_images(&req, track.clone()),
+        None => HttpResponse::NotFound().json(json!({
+            "error": { "status": 404, "message": "Non existing id" }
+        })),
+    }
+}
+
+// Pochette servie par le faux serveur à la place du CDN de Spotify
+#[get("/image/{id}")]
+async fn get_image(state: web::Data<MockState>) -> HttpResponse {
+    state.image_downloads.fetch_add(1, Ordering::SeqCst);
+
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+        .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+        .expect("unable to encode mock image");
+    HttpResponse::Ok().content_type("image/png").body(data)
+}
+
+// Les URL d'images des fixtures pointent vers ce serveur, pour rester hors ligne
+fn with_local_images(req: &HttpRequest, body: Value) -> HttpResponse {
+    let local = format!("http://{}/image/", req.connection_info().host());
+    let body = body.to_string().replace("https://i.scdn.co/image/", &local);
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(body)
+}
+
+fn check_bearer(req: &HttpRequest) -> Option<HttpResponse> {
+    let authorized = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == "Bearer mock-access-token");
+
+    if authorized {
+        None
+    } else {
+        Some(HttpResponse::Unauthorized().json(json!({
+            "error": { "status": 401, "message": "Invalid access token" }
+        })))
+    }
+}
+
+// Recherche volontairement large : le titre ou un des artistes apparaît dans la requête
+fn matches(track: &Value, q: &str) -> bool {
+    let q = q.to_lowercase();
+
+    if let Some(isrc) = q.trim().strip_prefix("isrc:") {
+        return track["external_ids"]["isrc"]
+            .as_str()
+            .is_some_and(|value| value.eq_ignore_ascii_case(isrc));
+    }
+
+    let name = track["name"].as_str().unwrap_or("").to_lowercase();
+    let artist_match = track["artists"].as_array().is_some_and(|artists| {
+        artists.iter().any(|artist| {
+            let artist_name = artist["name"].as_str().unwrap_or("").to_lowercase();
+            q.contains(&artist_name)
+        })
+    });
+
+    q.contains(&name) || artist_match
+}
@@ -1,26 +1,36 @@
 use actix_web::{get, web, HttpResponse, Responder};
 use base64::{engine::general_purpose, Engine};
-use serde_json::{json, Value};
-use std::error::Error;
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Method,
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, RETRY_AFTER},
+    Method, StatusCode,
 };
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
 
-use crate::{
-    data::models::{SearchQuery, TokenData, TrackQuery, SpotifyErrorWrapper, SpotifySearchResponse},
-    settings::env::{SPOTIY_ID, SPOTIY_SECRET},
+use crate::data::models::{
+    SearchQuery, SpotifyErrorWrapper, SpotifySearchResponse, TokenData, TrackQuery,
 };
 
 const ROOT_URL: &str = "https://api.spotify.com/v1/";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+const MAX_RETRIES: u32 = 5;
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
 
 // Controllers
 
 #[get("/get")]
-pub async fn spotify_get(q: web::Query<TrackQuery>) -> impl Responder {
+pub async fn spotify_get(
+    spotify: web::Data<SpotifyClient>,
+    q: web::Query<TrackQuery>,
+) -> impl Responder {
     if !q.endpoint.is_empty() {
         let search_query = format!("{}/{}", q.endpoint, q.id);
-        handle_spotify_request(Method::GET, &search_query, "get").await
+        handle_spotify_request(&spotify, Method::GET, &search_query, "get").await
     } else {
         HttpResponse::BadRequest().json(json!({
             "message": "Error endpoint is empty"
@@ -29,17 +39,21 @@ pub async fn spotify_get(q: web::Query<TrackQuery>) -> impl Responder {
 }
 
 #[get("/search")]
-pub async fn spotify_search(q: web::Query<SearchQuery>) -> impl Responder {
+pub async fn spotify_search(
+    spotify: web::Data<SpotifyClient>,
+    q: web::Query<SearchQuery>,
+) -> impl Responder {
     let search_query = format!("search?q={}&type=track&limit=1", q.query);
-    handle_spotify_request(Method::GET, &search_query, "search").await
+    handle_spotify_request(&spotify, Method::GET, &search_query, "search").await
 }
 
 async fn handle_spotify_request(
+    spotify: &SpotifyClient,
     method: reqwest::Method,
     endpoint: &str,
     service: &str,
 ) -> HttpResponse {
-    match send_spotify_request(method, endpoint).await {
+    match spotify.send(method, endpoint).await {
         Ok(body) => {
             if service == "search" {
                 let parsed_json: Result<SpotifySearchResponse, serde_json::Error> =
@@ -80,81 +94,327 @@ async fn handle_spotify_request(
     }
 }
 
-pub async fn send_spotify_request(method: reqwest::Method, endpoint: &str) -> Result<String, Box<dyn Error>> {
-    let token_data = match get_spotify_token().await {
-        Ok(data) => data,
-        Err(err) => return Err(err.into()),
-    };
-
-    let access_token = token_data.access_token;
-
-    let client = reqwest::Client::new();
-    let url = format!("{}{}", ROOT_URL, endpoint);
-
-    let response = match client
-        .request(method, &url)
-        .header(AUTHORIZATION, format!("Bearer {}", access_token))
-        .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(err) => return Err(err.into()),
-    };
-
-    let status = response.status();
-    let response_text = match response.text().await {
-        Ok(text) => text,
-        Err(err) => return Err(err.into()),
-    };
-
-    if status.is_success() {
-        Ok(response_text)
-    } else {
-        let error: Result<SpotifyErrorWrapper, _> = serde_json::from_str(&response_text);
-        match error {
-            Ok(parsed_error) => {
-                let error_message = format!(
-                    "Error fetching data from Spotify API: {}",
-                    parsed_error.error.message
-                );
-                Err(error_message.into())
+// Les URL peuvent pointer vers un serveur de test à la place de Spotify
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotifyConfig {
+    pub api_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub requests_per_second: f64,
+}
+
+impl Default for SpotifyConfig {
+    fn default() -> SpotifyConfig {
+        SpotifyConfig {
+            api_url: ROOT_URL.to_string(),
+            token_url: TOKEN_URL.to_string(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            requests_per_second: DEFAULT_REQUESTS_PER_SECOND,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SpotifyClient {
+    inner: Arc<SpotifyInner>,
+}
+
+struct SpotifyInner {
+    http: reqwest::Client,
+    config: SpotifyConfig,
+    token: Mutex<Option<CachedToken>>,
+    budget: Mutex<RequestBudget>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+// Espacement minimal entre deux requêtes, repoussé quand Spotify demande d'attendre
+struct RequestBudget {
+    interval: Duration,
+    next_slot: Instant,
+}
+
+impl SpotifyClient {
+    pub fn new(config: SpotifyConfig) -> SpotifyClient {
+        let interval = Duration::from_secs_f64(1.0 / config.requests_per_second.max(0.01));
+
+        SpotifyClient {
+            inner: Arc::new(SpotifyInner {
+                http: reqwest::Client::new(),
+                config,
+                token: Mutex::new(None),
+                budget: Mutex::new(RequestBudget {
+                    interval,
+                    next_slot: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    // Sans identifiants, aucune requête n'est envoyée : les titres restent locaux
+    pub fn is_configured(&self) -> bool {
+        let config = &self.inner.config;
+        !config.client_id.is_empty() && !config.client_secret.is_empty()
+    }
+
+    pub async fn send(&self, method: Method, endpoint: &str) -> Result<String, Box<dyn Error>> {
+        let url = format!("{}{}", self.inner.config.api_url, endpoint);
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_slot().await;
+            let access_token = self.token().await?;
+
+            let response = self
+                .inner
+                .http
+                .request(method.clone(), &url)
+                .header(AUTHORIZATION, format!("Bearer {}", access_token))
+                .send()
+                .await?;
+
+            let status = response.status();
+            if attempt < MAX_RETRIES {
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .map(Duration::from_secs)
+                        .unwrap_or_else(|| backoff(attempt));
+                    eprintln!("Spotify rate limit reached, retrying in {:?}", retry_after);
+                    self.pause(retry_after).await;
+                    attempt += 1;
+                    continue;
+                }
+
+                if status == StatusCode::UNAUTHORIZED {
+                    *self.inner.token.lock().await = None;
+                    attempt += 1;
+                    continue;
+                }
+
+                if status.is_server_error() {
+                    self.pause(backoff(attempt)).await;
+                    attempt += 1;
+                    continue;
+                }
             }
-            Err(err) => {
-                let error_message = format!(
-                    "Error fetching data from Spotify API:\n status code: {}\n error: {}",
-                    status, err
-                );
-                Err(error_message.into())
+
+            let response_text = response.text().await?;
+
+            return if status.is_success() {
+                Ok(response_text)
+            } else {
+                let error: Result<SpotifyErrorWrapper, _> = serde_json::from_str(&response_text);
+                match error {
+                    Ok(parsed_error) => {
+                        let error_message = format!(
+                            "Error fetching data from Spotify API ({}): {}",
+                            parsed_error.error.status, parsed_error.error.message
+                        );
+                        Err(error_message.into())
+                    }
+                    Err(err) => {
+                        let error_message = format!(
+                            "Error fetching data from Spotify API:\n status code: {}\n error: {}",
+                            status, err
+                        );
+                        Err(error_message.into())
+                    }
+                }
+            };
+        }
+    }
+
+    // Images du CDN de Spotify : ni jeton ni budget de l'API
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let response = self.inner.http.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    // Le jeton est réutilisé jusqu'à son expiration, avec une marge pour les requêtes en cours
+    async fn token(&self) -> Result<String, Box<dyn Error>> {
+        let mut token = self.inner.token.lock().await;
+
+        if let Some(cached) = token.as_ref() {
+            if cached.expires_at > Instant::now() + TOKEN_EXPIRY_MARGIN {
+                return Ok(cached.access_token.clone());
             }
         }
+
+        let token_data = self.get_spotify_token().await?;
+        let access_token = token_data.access_token.clone();
+        *token = Some(CachedToken {
+            access_token: token_data.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token_data.expires_in.max(0) as u64),
+        });
+
+        Ok(access_token)
+    }
+
+    async fn wait_for_slot(&self) {
+        let slot = {
+            let mut budget = self.inner.budget.lock().await;
+            let slot = budget.next_slot.max(Instant::now());
+            budget.next_slot = slot + budget.interval;
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+
+    async fn pause(&self, duration: Duration) {
+        let mut budget = self.inner.budget.lock().await;
+        budget.next_slot = budget.next_slot.max(Instant::now() + duration);
+    }
+
+    async fn get_spotify_token(&self) -> Result<TokenData, Box<dyn Error>> {
+        let config = &self.inner.config;
+        let base64_credentials = general_purpose::STANDARD
+            .encode(format!("{}:{}", config.client_id, config.client_secret));
+
+        let mut headers = HeaderMap::new();
+        let authorization_header_value =
+            match HeaderValue::from_str(&format!("Basic {}", base64_credentials)) {
+                Ok(value) => value,
+                Err(e) => return Err(Box::new(e) as Box<dyn Error>),
+            };
+
+        headers.insert(AUTHORIZATION, authorization_header_value);
+
+        let token_response = self
+            .inner
+            .http
+            .post(&config.token_url)
+            .headers(headers)
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        let token_data = serde_json::from_str(&token_response)?;
+
+        Ok(token_data)
     }
 }
 
-async fn get_spotify_token() -> Result<TokenData, Box<dyn Error>> {
-    let base64_credentials =
-        general_purpose::STANDARD.encode(format!("{}:{}", SPOTIY_ID, SPOTIY_SECRET));
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(6))
+}
 
-    let mut headers = HeaderMap::new();
-    let authorization_header_value =
-        match HeaderValue::from_str(&format!("Basic {}", base64_credentials)) {
-            Ok(value) => value,
-            Err(e) => return Err(Box::new(e) as Box<dyn Error>),
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockSpotify;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn search_route_uses_configured_urls() {
+        let mock = MockSpotify::start().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(mock.client()))
+                .service(spotify_search),
+        )
+        .await;
 
-    headers.insert(AUTHORIZATION, authorization_header_value);
+        let req = test::TestRequest::get()
+            .uri("/search?query=Daft%20Punk%20One%20More%20Time")
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
 
-    let client = reqwest::Client::new();
+        assert_eq!(
+            body["result"]["tracks"]["items"][0]["id"],
+            "0DiWol3AO6WpXZgp0goxAV"
+        );
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn token_is_reused_until_it_expires() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+
+        spotify
+            .send(Method::GET, "search?q=Daft Punk One More Time&type=track")
+            .await
+            .unwrap();
+        spotify
+            .send(Method::GET, "tracks/0DiWol3AO6WpXZgp0goxAV")
+            .await
+            .unwrap();
+
+        assert_eq!(mock.token_requests(), 1);
+        mock.stop().await;
+    }
 
-    let token_response = client
-        .post("https://accounts.spotify.com/api/token")
-        .headers(headers)
-        .form(&[("grant_type", "client_credentials")])
-        .send()
-        .await?
-        .text()
-        .await?;
+    #[actix_web::test]
+    async fn token_is_refreshed_when_it_expires() {
+        let mock = MockSpotify::start().await;
+        // Validité plus courte que la marge : le jeton est considéré expiré dès sa réception
+        mock.token_lifetime(TOKEN_EXPIRY_MARGIN.as_secs() / 2);
+        let spotify = mock.client();
 
-    let token_data = serde_json::from_str(&token_response)?;
+        spotify
+            .send(Method::GET, "tracks/0DiWol3AO6WpXZgp0goxAV")
+            .await
+            .unwrap();
+        spotify
+            .send(Method::GET, "tracks/0DiWol3AO6WpXZgp0goxAV")
+            .await
+            .unwrap();
+        assert_eq!(mock.token_requests(), 2);
 
-    Ok(token_data)
+        mock.token_lifetime(3600);
+        spotify
+            .send(Method::GET, "tracks/0DiWol3AO6WpXZgp0goxAV")
+            .await
+            .unwrap();
+        spotify
+            .send(Method::GET, "tracks/0DiWol3AO6WpXZgp0goxAV")
+            .await
+            .unwrap();
+        assert_eq!(mock.token_requests(), 3);
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn rate_limited_requests_are_retried() {
+        let mock = MockSpotify::start().await;
+        mock.rate_limit(1);
+
+        let body = mock
+            .client()
+            .send(Method::GET, "search?q=Daft Punk One More Time&type=track")
+            .await
+            .unwrap();
+        let parsed: SpotifySearchResponse = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(mock.searches().len(), 2);
+        assert_eq!(parsed.tracks.items[0].name, "One More Time");
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn spotify_errors_are_reported() {
+        let mock = MockSpotify::start().await;
+
+        let err = mock
+            .client()
+            .send(Method::GET, "tracks/unknown")
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("Non existing id"));
+        mock.stop().await;
+    }
 }
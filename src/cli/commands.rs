@@ -0,0 +1,716 @@
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::api::spotify::SpotifyClient;
+use crate::data::jobs::ScanHandle;
+use crate::data::libraries::Library;
+use crate::data::models::{LibraryStats, MatchDecision, Page, ScanReport};
+use crate::data::playlist_files::{path_under, relative_path, write_playlist, PlaylistFormat};
+use crate::data::review::{apply_decision, fetch_spotify_track, review_items};
+use crate::data::utils::get_tracks_data;
+use crate::database::database::{migrate_file, Database};
+use crate::database::library::{library_stats, load_track};
+use crate::database::matches::load_candidates;
+use crate::database::playlists::{load_playlist, load_playlists};
+use crate::settings::settings::Settings;
+
+// Sans sous-commande, le serveur démarre comme avant
+#[derive(Parser)]
+#[command(
+    name = "n",
+    version,
+    about = "Music library server and headless library management"
+)]
+pub struct Cli {
+    #[arg(long, global = true, help = "Print results as JSON on stdout")]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Start the HTTP server (default)")]
+    Serve,
+    #[command(about = "Scan one library")]
+    Scan {
+        #[arg(help = "Library id from the settings")]
+        library: String,
+        #[arg(long, help = "Only read files whose size or modification time changed")]
+        changed_only: bool,
+    },
+    #[command(about = "Scan every enabled library")]
+    Rescan {
+        #[arg(long, help = "Only read files whose size or modification time changed")]
+        changed_only: bool,
+    },
+    #[command(about = "Count indexed tracks, albums, artists and playlists")]
+    Stats,
+    #[command(about = "List tracks waiting for a match decision, or decide one")]
+    MatchReview {
+        #[command(subcommand)]
+        action: Option<ReviewAction>,
+    },
+    #[command(about = "Write a playlist as m3u8 or xspf")]
+    ExportPlaylist {
+        id: String,
+        #[arg(long, default_value = "m3u8")]
+        format: String,
+        #[arg(short, long, help = "File to write, stdout otherwise")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Database maintenance")]
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReviewAction {
+    #[command(about = "Accept one of the candidates proposed by the scan")]
+    Accept { track: String, spotify_id: String },
+    #[command(about = "Match the file to any Spotify track")]
+    Match { track: String, spotify_id: String },
+    #[command(about = "Keep the file as a local track")]
+    Local { track: String },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    #[command(about = "Apply pending migrations")]
+    Migrate,
+}
+
+#[derive(Serialize)]
+struct LibraryScan {
+    library: String,
+    report: Option<ScanReport>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    #[serde(flatten)]
+    total: LibraryStats,
+    playlists: usize,
+    libraries: Vec<LibraryCount>,
+}
+
+#[derive(Serialize)]
+struct LibraryCount {
+    id: String,
+    name: String,
+    #[serde(flatten)]
+    stats: LibraryStats,
+}
+
+// Code de sortie de la commande ; le résultat va sur `out`, en JSON ou lisible, les erreurs sur `err`
+pub async fn execute(
+    command: Command,
+    json: bool,
+    settings: &Settings,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> i32 {
+    match run(command, json, settings, out).await {
+        Ok(()) => 0,
+        Err(message) => {
+            let _ = if json {
+                writeln!(err, "{}", json!({ "message": message }))
+            } else {
+                writeln!(err, "{}", message)
+            };
+            1
+        }
+    }
+}
+
+async fn run(
+    command: Command,
+    json: bool,
+    settings: &Settings,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    match command {
+        Command::Serve => unreachable!("the server is started by main"),
+        Command::Scan {
+            library,
+            changed_only,
+        } => {
+            let library = settings
+                .libraries
+                .get(&library)
+                .ok_or_else(|| format!("Library not found: {}", library))?;
+            scan(settings, vec![library], changed_only, json, out).await
+        }
+        Command::Rescan { changed_only } => {
+            let libraries: Vec<&Library> = settings.libraries.enabled().collect();
+            if libraries.is_empty() {
+                return Err("No library configured".to_string());
+            }
+            scan(settings, libraries, changed_only, json, out).await
+        }
+        Command::Stats => stats(settings, json, out),
+        Command::MatchReview { action: None } => review_list(settings, json, out),
+        Command::MatchReview {
+            action: Some(action),
+        } => review_decide(settings, action, json, out).await,
+        Command::ExportPlaylist { id, format, output } => {
+            export_playlist(settings, &id, &format, output.as_deref(), json, out)
+        }
+        Command::Db {
+            command: DbCommand::Migrate,
+        } => {
+            let path = &settings.database.path;
+            let (before, after) = migrate_file(path).map_err(|err| format!("{}: {}", path, err))?;
+            print(
+                out,
+                json,
+                &json!({ "database": path, "from": before, "to": after }),
+                |out| {
+                    if before == after {
+                        writeln!(out, "{}: up to date (version {})", path, after)
+                    } else {
+                        writeln!(
+                            out,
+                            "{}: migrated from version {} to {}",
+                            path, before, after
+                        )
+                    }
+                },
+            )
+        }
+    }
+}
+
+fn open(settings: &Settings) -> Result<Database, String> {
+    let path = &settings.database.path;
+    Database::open(path).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn print<T: Serialize>(
+    out: &mut dyn Write,
+    json: bool,
+    value: &T,
+    human: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), String> {
+    if json {
+        let output = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+        writeln!(out, "{}", output).map_err(|err| err.to_string())
+    } else {
+        human(out).map_err(|err| err.to_string())
+    }
+}
+
+// Même analyse que les scans lancés par l'API ; un dossier en échec n'arrête pas les suivants
+async fn scan(
+    settings: &Settings,
+    libraries: Vec<&Library>,
+    changed_only: bool,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let db = open(settings)?;
+    let spotify = SpotifyClient::new(settings.spotify.clone());
+
+    let mut scans = Vec::new();
+    for library in libraries {
+        let result =
+            get_tracks_data(&db, &spotify, library, changed_only, &ScanHandle::default()).await;
+        if !json {
+            print_scan(out, library, &result).map_err(|err| err.to_string())?;
+        }
+
+        scans.push(LibraryScan {
+            library: library.id.clone(),
+            error: result.as_ref().err().cloned(),
+            report: result.ok(),
+        });
+    }
+
+    print(out, json, &json!({ "scans": scans }), |_| Ok(()))?;
+
+    let failed = scans.iter().filter(|scan| scan.error.is_some()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} scans failed", failed, scans.len()));
+    }
+
+    Ok(())
+}
+
+fn print_scan(
+    out: &mut dyn Write,
+    library: &Library,
+    result: &Result<ScanReport, String>,
+) -> io::Result<()> {
+    match result {
+        Ok(report) => {
+            writeln!(
+                out,
+                "{}: {} added, {} changed, {} removed, {} unchanged, {} to review, {} failed",
+                library.name,
+                report.added,
+                report.changed,
+                report.removed,
+                report.unchanged,
+                report.needs_review,
+                report.failures.len()
+            )?;
+            for failure in &report.failures {
+                writeln!(out, "  {}: {}", failure.path, failure.message)?;
+            }
+            for playlist in &report.playlists {
+                writeln!(
+                    out,
+                    "  {}: {} tracks, {} unresolved",
+                    playlist.path,
+                    playlist.tracks,
+                    playlist.unresolved.len()
+                )?;
+            }
+            Ok(())
+        }
+        Err(err) => writeln!(out, "{}: {}", library.name, err),
+    }
+}
+
+fn stats(settings: &Settings, json: bool, out: &mut dyn Write) -> Result<(), String> {
+    let db = open(settings)?;
+    let conn = db.connection();
+
+    let mut libraries = Vec::new();
+    for library in settings.libraries.list() {
+        libraries.push(LibraryCount {
+            id: library.id.clone(),
+            name: library.name.clone(),
+            stats: library_stats(&conn, &library.prefix()).map_err(|err| err.to_string())?,
+        });
+    }
+    let stats = Stats {
+        total: library_stats(&conn, "").map_err(|err| err.to_string())?,
+        playlists: load_playlists(&conn, &settings.libraries.prefixes())
+            .map_err(|err| err.to_string())?
+            .len(),
+        libraries,
+    };
+
+    print(out, json, &stats, |out| {
+        let mut line = |name: &str, stats: &LibraryStats| {
+            writeln!(
+                out,
+                "{}: {} tracks ({} local, {} to review), {} albums, {} artists",
+                name,
+                stats.tracks,
+                stats.local_tracks,
+                stats.to_review,
+                stats.albums,
+                stats.artists
+            )
+        };
+        line("All", &stats.total)?;
+        for library in &stats.libraries {
+            line(&library.name, &library.stats)?;
+        }
+        writeln!(out, "Playlists: {}", stats.playlists)
+    })
+}
+
+fn review_list(settings: &Settings, json: bool, out: &mut dyn Write) -> Result<(), String> {
+    let db = open(settings)?;
+    let (items, _) = review_items(
+        &db.connection(),
+        &settings.libraries.prefixes(),
+        &Page::default(),
+    )
+    .map_err(|err| err.to_string())?;
+
+    print(out, json, &json!({ "tracks": items }), |out| {
+        if items.is_empty() {
+            writeln!(out, "Nothing to review")?;
+        }
+        for item in &items {
+            writeln!(out, "{}  {}", item.track.id, item.track.path)?;
+            for candidate in &item.candidates {
+                writeln!(
+                    out,
+                    "    {:.2}  {}  {} - {}",
+                    candidate.score,
+                    candidate.track.id,
+                    candidate.track.artist,
+                    candidate.track.name
+                )?;
+            }
+        }
+        Ok(())
+    })
+}
+
+async fn review_decide(
+    settings: &Settings,
+    action: ReviewAction,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let db = open(settings)?;
+    let find_track = |id: &str| {
+        load_track(&db.connection(), id)
+            .map_err(|err| err.to_string())?
+            .filter(|track| {
+                settings
+                    .libraries
+                    .containing(Path::new(&track.path))
+                    .is_some()
+            })
+            .ok_or_else(|| format!("Track not found: {}", id))
+    };
+
+    let (current, decision) = match action {
+        ReviewAction::Accept { track, spotify_id } => {
+            let current = find_track(&track)?;
+            let candidate = load_candidates(&db.connection(), &current.path)
+                .map_err(|err| err.to_string())?
+                .into_iter()
+                .find(|candidate| candidate.track.id == spotify_id)
+                .ok_or_else(|| format!("Candidate not found: {}", spotify_id))?;
+            (current, MatchDecision::Spotify(Box::new(candidate.track)))
+        }
+        ReviewAction::Match { track, spotify_id } => {
+            let current = find_track(&track)?;
+            let spotify = SpotifyClient::new(settings.spotify.clone());
+            let spotify_track = fetch_spotify_track(&spotify, &spotify_id).await?;
+            (current, MatchDecision::Spotify(Box::new(spotify_track)))
+        }
+        ReviewAction::Local { track } => (find_track(&track)?, MatchDecision::Local),
+    };
+
+    let track = apply_decision(&mut db.connection(), &current, decision)?;
+    print(out, json, &track, |out| {
+        if track.is_local {
+            writeln!(out, "{}: kept as a local track", track.path)
+        } else {
+            writeln!(
+                out,
+                "{}: {} - {} ({})",
+                track.path, track.artist, track.name, track.id
+            )
+        }
+    })
+}
+
+// Les chemins sont écrits relativement au dossier du fichier produit
+fn export_playlist(
+    settings: &Settings,
+    id: &str,
+    format: &str,
+    output: Option<&Path>,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let format = PlaylistFormat::from_name(format)
+        .ok_or("Unknown playlist format, expected m3u8 or xspf")?;
+    let db = open(settings)?;
+    let playlist = load_playlist(&db.connection(), id, &settings.libraries.prefixes())
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| format!("Playlist not found: {}", id))?;
+
+    // Sur la sortie standard, comme pour l'API : dossier du fichier d'origine ou de chaque titre
+    let Some(output) = output else {
+        let base_dir = playlist
+            .source
+            .as_deref()
+            .and_then(|source| Path::new(source).parent());
+        let content = write_playlist(&playlist, format, |track| match base_dir {
+            Some(base_dir) => path_under(base_dir, track),
+            None => settings.libraries.relative(&track.path).map(PathBuf::from),
+        });
+        return print(
+            out,
+            json,
+            &json!({ "playlist": id, "content": content }),
+            |out| write!(out, "{}", content),
+        );
+    };
+
+    let output = std::path::absolute(output).map_err(|err| err.to_string())?;
+    let base_dir = output.parent().unwrap_or(Path::new("/"));
+    let content = write_playlist(&playlist, format, |track| {
+        Some(relative_path(base_dir, Path::new(&track.path)))
+    });
+    fs::write(&output, content).map_err(|err| format!("{}: {}", output.display(), err))?;
+
+    print(
+        out,
+        json,
+        &json!({ "playlist": id, "path": output, "tracks": playlist.tracks.len() }),
+        |out| {
+            writeln!(
+                out,
+                "{}: {} tracks written to {}",
+                playlist.name,
+                playlist.tracks.len(),
+                output.display()
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockSpotify;
+    use crate::data::libraries::Libraries;
+    use crate::data::models::{FileFingerprint, Item, MatchCandidate, Playlist};
+    use crate::data::tags::track_key;
+    use crate::database::database::DatabaseConfig;
+    use crate::database::files::save_fingerprint;
+    use crate::database::library::save_track;
+    use crate::database::matches::save_candidates;
+    use crate::database::playlists::create_playlist;
+    use clap::CommandFactory;
+    use serde_json::Value;
+
+    // Base dans le dossier de la bibliothèque, Spotify simulé
+    fn test_settings(dir: &Path, mock: &MockSpotify) -> Settings {
+        Settings {
+            database: DatabaseConfig {
+                path: dir.join("n.db").to_string_lossy().into_owned(),
+            },
+            spotify: mock.config(),
+            libraries: Libraries::new(vec![Library::at(dir)]).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // Code de sortie, stdout et stderr de la commande
+    async fn call(settings: &Settings, args: &[&str]) -> (i32, String, String) {
+        let cli = Cli::try_parse_from(args).unwrap();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = execute(cli.command.unwrap(), cli.json, settings, &mut out, &mut err).await;
+
+        (
+            status,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    // Titre local indexé sous la bibliothèque, en attente de revue
+    fn local_track(settings: &Settings, name: &str) -> Item {
+        let library = settings.libraries.get("test").unwrap();
+        let path = format!("{}{}.mp3", library.prefix(), name);
+        let track = Item {
+            id: track_key(&path),
+            name: name.to_string(),
+            artist: "Daft Punk".to_string(),
+            path,
+            is_local: true,
+            ..Default::default()
+        };
+
+        let db = open(settings).unwrap();
+        let conn = db.connection();
+        save_track(&conn, &track).unwrap();
+        save_fingerprint(
+            &conn,
+            &FileFingerprint {
+                path: track.path.clone(),
+                size: 0,
+                mtime: 0,
+                hash: String::new(),
+            },
+        )
+        .unwrap();
+        track
+    }
+
+    #[actix_web::test]
+    async fn stats_and_playlist_exports_print_json() {
+        let mock = MockSpotify::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let settings = test_settings(dir.path(), &mock);
+        let track = local_track(&settings, "demo");
+        create_playlist(
+            &open(&settings).unwrap().connection(),
+            &Playlist {
+                id: "road-trip".to_string(),
+                name: "Road Trip".to_string(),
+                description: String::new(),
+                cover: None,
+                source: None,
+                created_at: 0,
+                updated_at: 0,
+                rules: None,
+                track_ids: vec![track.id.clone()],
+                tracks: Vec::new(),
+            },
+        )
+        .unwrap();
+
+        let (status, out, err) = call(&settings, &["n", "--json", "stats"]).await;
+        assert_eq!((status, err.as_str()), (0, ""));
+        let stats: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(stats["tracks"], 1);
+        assert_eq!(stats["to_review"], 1);
+        assert_eq!(stats["playlists"], 1);
+        assert_eq!(stats["libraries"][0]["id"], "test");
+        assert_eq!(stats["libraries"][0]["local_tracks"], 1);
+
+        let (status, out, _) =
+            call(&settings, &["n", "export-playlist", "road-trip", "--json"]).await;
+        assert_eq!(status, 0);
+        let export: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(export["playlist"], "road-trip");
+        assert!(export["content"].as_str().unwrap().contains("demo.mp3"));
+
+        let output = dir.path().join("road-trip.xspf");
+        let (status, out, _) = call(
+            &settings,
+            &[
+                "n",
+                "--json",
+                "export-playlist",
+                "road-trip",
+                "--format",
+                "xspf",
+                "--output",
+                &output.to_string_lossy(),
+            ],
+        )
+        .await;
+        assert_eq!(status, 0);
+        let export: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(export["tracks"], 1);
+        assert!(fs::read_to_string(&output).unwrap().contains("<location>"));
+
+        // Erreurs : code 1, message JSON sur stderr et rien sur stdout
+        let (status, out, err) =
+            call(&settings, &["n", "--json", "export-playlist", "missing"]).await;
+        assert_eq!((status, out.as_str()), (1, ""));
+        let error: Value = serde_json::from_str(&err).unwrap();
+        assert_eq!(error["message"], "Playlist not found: missing");
+
+        let (status, _, err) = call(
+            &settings,
+            &["n", "export-playlist", "road-trip", "--format", "wav"],
+        )
+        .await;
+        assert_eq!(status, 1);
+        assert_eq!(err, "Unknown playlist format, expected m3u8 or xspf\n");
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn match_review_lists_and_decides_tracks() {
+        let mock = MockSpotify::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let settings = test_settings(dir.path(), &mock);
+        let first = local_track(&settings, "one-more-time");
+        let second = local_track(&settings, "harder");
+        let candidate = Item {
+            id: "candidate".to_string(),
+            name: "One More Time".to_string(),
+            ..Default::default()
+        };
+        save_candidates(
+            &open(&settings).unwrap().connection(),
+            &first.path,
+            &[MatchCandidate {
+                score: 0.7,
+                track: candidate,
+            }],
+        )
+        .unwrap();
+
+        let (status, out, _) = call(&settings, &["n", "--json", "match-review"]).await;
+        assert_eq!(status, 0);
+        let queue: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(queue["tracks"].as_array().unwrap().len(), 2);
+        assert_eq!(queue["tracks"][1]["track"]["id"], first.id);
+        assert_eq!(
+            queue["tracks"][1]["candidates"][0]["track"]["id"],
+            "candidate"
+        );
+
+        let (status, out, _) = call(
+            &settings,
+            &[
+                "n",
+                "--json",
+                "match-review",
+                "accept",
+                &first.id,
+                "candidate",
+            ],
+        )
+        .await;
+        assert_eq!(status, 0);
+        let track: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(track["id"], first.id);
+        assert_eq!(track["spotify_id"], "candidate");
+        assert_eq!(track["match_method"], "manual");
+
+        // Titre choisi librement : récupéré auprès de Spotify
+        let (status, out, _) = call(
+            &settings,
+            &[
+                "n",
+                "--json",
+                "match-review",
+                "match",
+                &second.id,
+                "5W3cjX2J3tjhG8zb6u0qHn",
+            ],
+        )
+        .await;
+        assert_eq!(status, 0);
+        let track: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(track["name"], "Harder, Better, Faster, Stronger");
+
+        let (status, out, _) = call(&settings, &["n", "--json", "match-review"]).await;
+        assert_eq!(status, 0);
+        assert_eq!(
+            serde_json::from_str::<Value>(&out).unwrap()["tracks"],
+            json!([])
+        );
+
+        let (status, out, err) = call(
+            &settings,
+            &["n", "--json", "match-review", "local", "missing"],
+        )
+        .await;
+        assert_eq!((status, out.as_str()), (1, ""));
+        let error: Value = serde_json::from_str(&err).unwrap();
+        assert_eq!(error["message"], "Track not found: missing");
+        mock.stop().await;
+    }
+
+    #[test]
+    fn subcommands_parse_with_a_global_json_flag() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["n", "rescan", "--changed-only", "--json"]).unwrap();
+        assert!(cli.json);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Rescan { changed_only: true })
+        ));
+
+        let cli =
+            Cli::try_parse_from(["n", "--json", "match-review", "accept", "t1", "s1"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::MatchReview {
+                action: Some(ReviewAction::Accept { .. })
+            })
+        ));
+
+        assert!(Cli::try_parse_from(["n"]).unwrap().command.is_none());
+        assert!(Cli::try_parse_from(["n", "scan"]).is_err());
+        assert!(Cli::try_parse_from(["n", "db", "migrate"]).is_ok());
+    }
+}
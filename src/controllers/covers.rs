@@ -0,0 +1,297 @@
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
+use serde_json::json;
+
+use super::errors::server_error;
+use crate::data::covers::{thumbnail, thumbnail_size, Cover, ThumbnailFormat, THUMBNAIL_SIZES};
+use crate::data::libraries::Libraries;
+use crate::data::models::CoverQuery;
+use crate::database::{
+    covers::{load_album_cover, load_album_image, load_thumbnail, save_thumbnail},
+    database::Database,
+};
+
+// Pochette extraite des fichiers de l'album
+#[get("/albums/{id}/cover")]
+pub async fn get_album_cover(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Query(info): web::Query<CoverQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let cover = load_album_cover(&db.connection(), &id, &libraries.prefixes());
+    match cover {
+        Ok(Some(cover)) => serve_cover(&db, &req, cover, info).await,
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "message": "Cover not found"
+        })),
+        Err(err) => server_error(err),
+    }
+}
+
+// Copie locale d'une image Spotify de l'album, dans l'ordre de `images`
+#[get("/albums/{id}/images/{position}")]
+pub async fn get_album_image(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    path: web::Path<(String, i64)>,
+    web::Query(info): web::Query<CoverQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (id, position) = path.into_inner();
+
+    let image = load_album_image(&db.connection(), &id, position, &libraries.prefixes());
+    match image {
+        Ok(Some(cover)) => serve_cover(&db, &req, cover, info).await,
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "message": "Image not found"
+        })),
+        Err(err) => server_error(err),
+    }
+}
+
+// Image d'origine, ou miniature d'au moins `size` x `size` en JPEG ou WebP mise en cache
+async fn serve_cover(
+    db: &Database,
+    req: &HttpRequest,
+    cover: Cover,
+    info: CoverQuery,
+) -> HttpResponse {
+    let format = match info.format.as_deref().map(ThumbnailFormat::from_name) {
+        None => ThumbnailFormat::Jpeg,
+        Some(Some(format)) => format,
+        Some(None) => {
+            return HttpResponse::BadRequest().json(json!({
+                "message": "Unknown image format, expected jpeg or webp"
+            }))
+        }
+    };
+    let Some(requested) = info.size else {
+        return image_response(req, &cover.hash, &cover.mime_type, cover.data);
+    };
+    let Some(size) = thumbnail_size(requested) else {
+        return HttpResponse::BadRequest().json(json!({
+            "message": "Size must be between 1 and 1024",
+            "sizes": THUMBNAIL_SIZES,
+        }));
+    };
+
+    let etag = format!("{}-{}-{}", cover.hash, size, format.name());
+    let cached = load_thumbnail(&db.connection(), &cover.hash, size, format.name());
+    let data = match cached {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            let source = cover.data;
+            match web::block(move || thumbnail(&source, size, format)).await {
+                Ok(Ok(data)) => {
+                    if let Err(err) =
+                        save_thumbnail(&db.connection(), &cover.hash, size, format.name(), &data)
+                    {
+                        println!("{}", err);
+                    }
+                    data
+                }
+                Ok(Err(err)) => return server_error(err),
+                Err(err) => return server_error(err),
+            }
+        }
+        Err(err) => return server_error(err),
+    };
+
+    image_response(req, &etag, format.content_type(), data)
+}
+
+// Une pochette ne change pas sans changer d'empreinte : l'ETag suffit à la revalider
+fn image_response(
+    req: &HttpRequest,
+    etag: &str,
+    content_type: &str,
+    data: Vec<u8>,
+) -> HttpResponse {
+    let etag = format!("\"{}\"", etag);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"));
+
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(content_type).body(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::covers::{cover_from, find_cover};
+    use crate::data::libraries::Library;
+    use crate::data::models::{Album, Image, Item};
+    use crate::database::{
+        covers::{save_album_cover, save_album_image},
+        library::{load_album, save_track},
+    };
+    use actix_web::{http::StatusCode, test, App};
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    #[actix_web::test]
+    async fn covers_are_resized_and_revalidated() {
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(256, 128))
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        std::fs::write(dir.path().join("cover.png"), png).unwrap();
+
+        let cover = find_cover(&dir.path().join("01.mp3"), None).unwrap();
+        for (id, dir) in [("local", "music"), ("archived", "archive")] {
+            let track = Item {
+                id: format!("{}-track", id),
+                path: format!("/{}/01.mp3", dir),
+                album: Album {
+                    id: format!("{}-album", id),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+            save_album_cover(&db.connection(), &track.album.id, &cover).unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(Path::new("/music"))]).unwrap(),
+                ))
+                .service(get_album_cover),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/albums/local-album/cover?size=100&format=webp")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/webp"
+        );
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+        let body = test::read_body(res).await;
+        assert_eq!(image::load_from_memory(&body).unwrap().width(), 128);
+
+        // Toute taille du même palier partage la miniature et son ETag
+        let req = test::TestRequest::get()
+            .uri("/albums/local-album/cover?size=120&format=webp")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let thumbnails: i64 = db
+            .connection()
+            .query_row("SELECT COUNT(*) FROM cover_thumbnails", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(thumbnails, 1);
+
+        let req = test::TestRequest::get()
+            .uri("/albums/local-album/cover?size=5000")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Album inconnu, ou seulement présent hors des dossiers activés
+        for uri in ["/albums/unknown/cover", "/albums/archived-album/cover"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn spotify_images_are_served_once_cached() {
+        let db = Database::open(":memory:").unwrap();
+        let spotify_url = |id: &str| format!("https://i.scdn.co/image/{}", id);
+        let track = Item {
+            id: "spotify-track".to_string(),
+            path: "/music/01.mp3".to_string(),
+            album: Album {
+                id: "spotify-album".to_string(),
+                images: ["large", "small"]
+                    .map(|id| Image {
+                        url: spotify_url(id),
+                        ..Default::default()
+                    })
+                    .to_vec(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        save_track(&db.connection(), &track).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(Path::new("/music"))]).unwrap(),
+                ))
+                .service(get_album_image),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        // Pas encore téléchargée : l'URL de Spotify est renvoyée telle quelle
+        let album = load_album(&db.connection(), "spotify-album")
+            .unwrap()
+            .unwrap();
+        assert_eq!(album.images[0].url, spotify_url("large"));
+        assert_eq!(album.images[0].fallback_url, None);
+        let res = test::call_service(&app, get("/albums/spotify-album/images/0")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(300, 300))
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        let cover = cover_from(png.clone()).unwrap();
+        save_album_image(&db.connection(), "spotify-album", 0, &cover).unwrap();
+
+        let album = load_album(&db.connection(), "spotify-album")
+            .unwrap()
+            .unwrap();
+        assert_eq!(album.images[0].url, "/albums/spotify-album/images/0");
+        assert_eq!(album.images[0].fallback_url, Some(spotify_url("large")));
+        assert_eq!(album.images[1].url, spotify_url("small"));
+        assert_eq!(album.images[1].fallback_url, None);
+
+        let res = test::call_service(&app, get("/albums/spotify-album/images/0")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/png"
+        );
+        assert_eq!(test::read_body(res).await, png);
+
+        let body =
+            test::call_and_read_body(&app, get("/albums/spotify-album/images/0?size=64")).await;
+        assert_eq!(image::load_from_memory(&body).unwrap().width(), 64);
+
+        let res = test::call_service(&app, get("/albums/spotify-album/images/1")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,10 @@
+use actix_web::HttpResponse;
+use serde_json::json;
+
+// Erreur interne : le détail reste dans les journaux du serveur
+pub fn server_error(err: impl std::fmt::Display) -> HttpResponse {
+    println!("{}", err);
+    HttpResponse::InternalServerError().json(json!({
+        "message": "Erreur lors de la récupération des données"
+    }))
+}
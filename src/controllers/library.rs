@@ -0,0 +1,158 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde_json::json;
+
+use crate::api::spotify::SpotifyClient;
+use crate::data::{jobs::ScanJobs, libraries::Libraries, models::ScanRequest};
+use crate::database::database::Database;
+
+#[post("/scans")]
+pub async fn start_scan(
+    db: web::Data<Database>,
+    spotify: web::Data<SpotifyClient>,
+    jobs: web::Data<ScanJobs>,
+    libraries: web::Data<Libraries>,
+    web::Json(info): web::Json<ScanRequest>,
+) -> impl Responder {
+    let library = match libraries.get(&info.library) {
+        Some(library) => library.clone(),
+        None => {
+            return HttpResponse::NotFound().json(json!({
+                "message": "Library not found"
+            }))
+        }
+    };
+
+    match jobs.start(
+        db.get_ref().clone(),
+        spotify.get_ref().clone(),
+        library,
+        info.changed_only,
+    ) {
+        Ok(job) => HttpResponse::Accepted().json(job),
+        Err(err) => HttpResponse::Conflict().json(json!({
+            "message": err
+        })),
+    }
+}
+
+// Dossiers déclarés dans la configuration du serveur
+#[get("")]
+pub async fn get_libraries(libraries: web::Data<Libraries>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "libraries": libraries.list(),
+    }))
+}
+
+#[get("/scans")]
+pub async fn get_scans(jobs: web::Data<ScanJobs>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "scans": jobs.list(),
+    }))
+}
+
+#[get("/scans/{id}")]
+pub async fn get_scan(jobs: web::Data<ScanJobs>, id: web::Path<String>) -> impl Responder {
+    match jobs.get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(json!({
+            "message": "Scan not found"
+        })),
+    }
+}
+
+#[delete("/scans/{id}")]
+pub async fn cancel_scan(jobs: web::Data<ScanJobs>, id: web::Path<String>) -> impl Responder {
+    match jobs.cancel(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(json!({
+            "message": "Scan not found"
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockSpotify;
+    use crate::data::libraries::Library;
+    use actix_web::{http::StatusCode, test, App};
+    use audiotags::{AudioTagEdit, AudioTagWrite, Id3v2Tag};
+    use serde_json::Value;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn scans_run_once_per_library_and_can_be_cancelled() {
+        let mock = MockSpotify::start().await;
+        // La première recherche attend une seconde : l'analyse est encore en cours
+        mock.rate_limit(1);
+        let dir = tempfile::tempdir().unwrap();
+        for (name, title) in [("01.mp3", "One More Time"), ("02.mp3", "Aerodynamic")] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"").unwrap();
+            let mut tag = Id3v2Tag::new();
+            tag.set_artist("Daft Punk");
+            tag.set_title(title);
+            tag.write_to_path(path.to_str().unwrap()).unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Database::open(":memory:").unwrap()))
+                .app_data(web::Data::new(mock.client()))
+                .app_data(web::Data::new(ScanJobs::default()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(dir.path())]).unwrap(),
+                ))
+                .service(
+                    web::scope("/library")
+                        .service(start_scan)
+                        .service(get_scan)
+                        .service(cancel_scan),
+                ),
+        )
+        .await;
+        let start = || {
+            test::TestRequest::post()
+                .uri("/library/scans")
+                .set_json(json!({ "library": "test" }))
+                .to_request()
+        };
+
+        let res = test::call_service(&app, start()).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let job: Value = test::read_body_json(res).await;
+        assert_eq!(job["status"], "running");
+        let uri = format!("/library/scans/{}", job["id"].as_str().unwrap());
+
+        let res = test::call_service(&app, start()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::delete().uri(&uri).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut job = Value::Null;
+        for _ in 0..100 {
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            job = test::call_and_read_body_json(&app, req).await;
+            if job["status"] != "running" {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(job["status"], "cancelled");
+        assert_eq!(job["progress"]["total"], 2);
+        assert!(job["finished_at"].is_u64());
+
+        // Une fois l'analyse arrêtée, une nouvelle peut démarrer
+        let res = test::call_service(&app, start()).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+        let req = test::TestRequest::get()
+            .uri("/library/scans/unknown")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        mock.stop().await;
+    }
+}
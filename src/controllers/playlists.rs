@@ -0,0 +1,627 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{delete, get, patch, post, web, HttpResponse, Responder};
+use rusqlite::Connection;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::errors::server_error;
+use crate::data::libraries::Libraries;
+use crate::data::models::{
+    Playlist, PlaylistExportQuery, PlaylistMoveRequest, PlaylistRequest, PlaylistTracksRequest,
+    PlaylistUpdate,
+};
+use crate::data::playlist_files::{path_under, write_playlist, PlaylistFormat};
+use crate::database::{
+    database::Database,
+    files::now,
+    library::load_track,
+    playlists::{
+        create_playlist, delete_playlist, is_smart_playlist, load_entries, load_playlist,
+        load_playlists, mark_edited, save_entries, update_playlist, visible_entries,
+    },
+    rules::check_rules,
+};
+
+#[get("")]
+pub async fn get_playlists(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+) -> impl Responder {
+    let playlists = load_playlists(&db.connection(), &libraries.prefixes());
+    match playlists {
+        Ok(mut playlists) => {
+            playlists
+                .iter_mut()
+                .for_each(|playlist| libraries.expose_playlist(playlist));
+            HttpResponse::Ok().json(json!({
+                "playlists": playlists,
+            }))
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+#[post("")]
+pub async fn new_playlist(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    web::Json(info): web::Json<PlaylistRequest>,
+) -> impl Responder {
+    let name = info.name.trim().to_string();
+    if name.is_empty() {
+        return bad_request("Playlist name cannot be empty");
+    }
+    if let Some(Err(err)) = info.rules.as_ref().map(check_rules) {
+        return bad_request(&err);
+    }
+
+    let created_at = now();
+    let playlist = Playlist {
+        id: Uuid::new_v4().to_string(),
+        name,
+        description: info.description,
+        cover: info.cover,
+        source: None,
+        created_at,
+        updated_at: created_at,
+        rules: info.rules,
+        track_ids: Vec::new(),
+        tracks: Vec::new(),
+    };
+
+    let conn = db.connection();
+    match create_playlist(&conn, &playlist) {
+        Ok(()) => match load_playlist(&conn, &playlist.id, &libraries.prefixes()) {
+            Ok(Some(mut playlist)) => {
+                libraries.expose_playlist(&mut playlist);
+                HttpResponse::Created().json(playlist)
+            }
+            Ok(None) => not_found(),
+            Err(err) => server_error(err),
+        },
+        Err(err) => server_error(err),
+    }
+}
+
+#[get("/{id}")]
+pub async fn get_playlist(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+) -> impl Responder {
+    playlist_response(&db.connection(), &libraries, &id)
+}
+
+// Renomme la liste ou change sa description et sa pochette
+#[patch("/{id}")]
+pub async fn edit_playlist(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Json(info): web::Json<PlaylistUpdate>,
+) -> impl Responder {
+    let conn = db.connection();
+    let mut playlist = match load_playlist(&conn, &id, &libraries.prefixes()) {
+        Ok(Some(playlist)) => playlist,
+        Ok(None) => return not_found(),
+        Err(err) => return server_error(err),
+    };
+
+    if let Some(name) = info.name {
+        if name.trim().is_empty() {
+            return bad_request("Playlist name cannot be empty");
+        }
+        playlist.name = name.trim().to_string();
+    }
+    if let Some(description) = info.description {
+        playlist.description = description;
+    }
+    if let Some(cover) = info.cover {
+        playlist.cover = Some(cover).filter(|cover| !cover.is_empty());
+    }
+    if let Some(rules) = info.rules {
+        if playlist.rules.is_none() {
+            return bad_request("Only smart playlists have rules");
+        }
+        if let Err(err) = check_rules(&rules) {
+            return bad_request(&err);
+        }
+        playlist.rules = Some(rules);
+    }
+
+    match update_playlist(&conn, &playlist) {
+        Ok(()) => playlist_response(&conn, &libraries, &id),
+        Err(err) => server_error(err),
+    }
+}
+
+#[delete("/{id}")]
+pub async fn remove_playlist(db: web::Data<Database>, id: web::Path<String>) -> impl Responder {
+    let deleted = delete_playlist(&db.connection(), &id);
+    match deleted {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found(),
+        Err(err) => server_error(err),
+    }
+}
+
+// Ajoute des titres en fin de liste, ou à partir de `position`
+#[post("/{id}/tracks")]
+pub async fn add_playlist_tracks(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Json(info): web::Json<PlaylistTracksRequest>,
+) -> impl Responder {
+    edit_entries(
+        &mut db.connection(),
+        &libraries,
+        &id,
+        |conn, entries, visible| {
+            for track_id in &info.track_ids {
+                match load_track(conn, track_id) {
+                    Ok(Some(track)) if libraries.containing(Path::new(&track.path)).is_some() => {}
+                    Ok(_) => return Err(bad_request(&format!("Unknown track {}", track_id))),
+                    Err(err) => return Err(server_error(err)),
+                }
+            }
+
+            let position = info.position.unwrap_or(visible.len());
+            if position > visible.len() {
+                return Err(bad_request("Position out of range"));
+            }
+            let position = visible.get(position).copied().unwrap_or(entries.len());
+            entries.splice(position..position, info.track_ids.iter().cloned());
+            Ok(())
+        },
+    )
+}
+
+#[post("/{id}/tracks/move")]
+pub async fn move_playlist_track(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Json(info): web::Json<PlaylistMoveRequest>,
+) -> impl Responder {
+    edit_entries(
+        &mut db.connection(),
+        &libraries,
+        &id,
+        |_, entries, visible| {
+            if info.from >= visible.len() || info.to >= visible.len() {
+                return Err(bad_request("Position out of range"));
+            }
+            let track_id = entries.remove(visible[info.from]);
+            entries.insert(visible[info.to], track_id);
+            Ok(())
+        },
+    )
+}
+
+#[delete("/{id}/tracks/{position}")]
+pub async fn remove_playlist_track(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    path: web::Path<(String, usize)>,
+) -> impl Responder {
+    let (id, position) = path.into_inner();
+
+    edit_entries(
+        &mut db.connection(),
+        &libraries,
+        &id,
+        |_, entries, visible| {
+            if position >= visible.len() {
+                return Err(bad_request("Position out of range"));
+            }
+            entries.remove(visible[position]);
+            Ok(())
+        },
+    )
+}
+
+// Fichier M3U8 ou XSPF, chemins relatifs au dossier demandé, à celui du fichier d'origine ou au
+// dossier de chaque titre ; les titres hors du dossier de la liste sont omis
+#[get("/{id}/export")]
+pub async fn export_playlist(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Query(info): web::Query<PlaylistExportQuery>,
+) -> impl Responder {
+    let Some(format) = PlaylistFormat::from_name(info.format.as_deref().unwrap_or("m3u8")) else {
+        return bad_request("Unknown playlist format, expected m3u8 or xspf");
+    };
+
+    let playlist = load_playlist(&db.connection(), &id, &libraries.prefixes());
+    let playlist = match playlist {
+        Ok(Some(playlist)) => playlist,
+        Ok(None) => return not_found(),
+        Err(err) => return server_error(err),
+    };
+
+    let base_dir = match (&info.dir, &playlist.source) {
+        (Some(dir), _) => {
+            let Some(library) = info.library.as_deref().and_then(|id| libraries.get(id)) else {
+                return bad_request("Unknown library for the export folder");
+            };
+            match library.resolve(dir) {
+                Ok(dir) => Some(dir),
+                Err(err) => return bad_request(&err.to_string()),
+            }
+        }
+        (None, Some(source)) => Path::new(source).parent().map(Path::to_path_buf),
+        (None, None) => None,
+    };
+    let content = write_playlist(&playlist, format, |track| match &base_dir {
+        Some(base_dir) => path_under(base_dir, track),
+        None => libraries.relative(&track.path).map(PathBuf::from),
+    });
+    let filename = format!(
+        "{}.{}",
+        playlist.name.replace(['/', '\\', '"'], "_"),
+        format.extension()
+    );
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(content)
+}
+
+// Modifie l'ordre des titres dans une transaction, puis renvoie la liste complète.
+// Les positions reçues comptent les seuls titres visibles ; les autres entrées restent en place.
+fn edit_entries(
+    conn: &mut Connection,
+    libraries: &Libraries,
+    id: &str,
+    edit: impl FnOnce(&Connection, &mut Vec<String>, &[usize]) -> Result<(), HttpResponse>,
+) -> HttpResponse {
+    let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return server_error(err),
+    };
+
+    // Les titres d'une liste intelligente ne dépendent que de ses règles
+    match is_smart_playlist(&tx, id) {
+        Ok(Some(false)) => {}
+        Ok(Some(true)) => return bad_request("Smart playlists are updated from their rules"),
+        Ok(None) => return not_found(),
+        Err(err) => return server_error(err),
+    }
+
+    let mut entries = match load_entries(&tx, id) {
+        Ok(entries) => entries,
+        Err(err) => return server_error(err),
+    };
+    let visible: Vec<usize> = match visible_entries(&tx, id, &libraries.prefixes()) {
+        Ok(visible) => visible.into_iter().map(|(position, _)| position).collect(),
+        Err(err) => return server_error(err),
+    };
+    if let Err(response) = edit(&tx, &mut entries, &visible) {
+        return response;
+    }
+
+    match save_entries(&tx, id, &entries)
+        .and_then(|_| mark_edited(&tx, id))
+        .and_then(|_| tx.commit())
+    {
+        Ok(()) => playlist_response(conn, libraries, id),
+        Err(err) => server_error(err),
+    }
+}
+
+fn playlist_response(conn: &Connection, libraries: &Libraries, id: &str) -> HttpResponse {
+    match load_playlist(conn, id, &libraries.prefixes()) {
+        Ok(Some(mut playlist)) => {
+            libraries.expose_playlist(&mut playlist);
+            HttpResponse::Ok().json(playlist)
+        }
+        Ok(None) => not_found(),
+        Err(err) => server_error(err),
+    }
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "message": "Playlist not found"
+    }))
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "message": message
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::libraries::Library;
+    use crate::data::models::Item;
+    use crate::database::library::save_track;
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn playlist_entries_keep_their_order() {
+        let db = Database::open(":memory:").unwrap();
+        for (id, dir) in [
+            ("a", "music"),
+            ("b", "music"),
+            ("c", "music"),
+            ("x", "archive"),
+        ] {
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: format!("/{}/{}.mp3", dir, id),
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        // Un titre d'un dossier désactivé reste dans la liste sans être montré ni compté
+        let mut archive = Library::at(Path::new("/archive"));
+        archive.id = "archive".to_string();
+        archive.enabled = false;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(Path::new("/music")), archive]).unwrap(),
+                ))
+                .service(
+                    web::scope("/playlists")
+                        .service(new_playlist)
+                        .service(edit_playlist)
+                        .service(remove_playlist)
+                        .service(add_playlist_tracks)
+                        .service(move_playlist_track)
+                        .service(remove_playlist_track),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/playlists")
+            .set_json(json!({ "name": "Road trip" }))
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let id = created["id"].as_str().unwrap();
+        let uri = format!("/playlists/{}", id);
+        save_entries(&db.connection(), id, &["x".to_string()]).unwrap();
+
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks", uri))
+            .set_json(json!({ "track_ids": ["x"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks", uri))
+            .set_json(json!({ "track_ids": ["a", "c"] }))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks", uri))
+            .set_json(json!({ "track_ids": ["b"], "position": 1 }))
+            .to_request();
+        let playlist: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(playlist["track_ids"], json!(["a", "b", "c"]));
+        assert_eq!(playlist["tracks"][1]["name"], "B");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks/move", uri))
+            .set_json(json!({ "from": 2, "to": 0 }))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("{}/tracks/1", uri))
+            .to_request();
+        let playlist: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(playlist["track_ids"], json!(["c", "b"]));
+
+        // Un fichier associé à un autre titre Spotify garde son id dans la liste
+        let rematched = Item {
+            id: "c".to_string(),
+            path: "/music/c.mp3".to_string(),
+            spotify_id: Some("d".to_string()),
+            ..Default::default()
+        };
+        save_track(&db.connection(), &rematched).unwrap();
+
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .set_json(json!({ "name": "Summer" }))
+            .to_request();
+        let playlist: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(playlist["name"], "Summer");
+        assert_eq!(playlist["track_ids"], json!(["c", "b"]));
+        assert_eq!(playlist["tracks"][0]["spotify_id"], "d");
+        assert_eq!(load_entries(&db.connection(), id).unwrap(), ["x", "c", "b"]);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks", uri))
+            .set_json(json!({ "track_ids": ["missing"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::delete().uri(&uri).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .set_json(json!({}))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn smart_playlists_follow_their_rules() {
+        let db = Database::open(":memory:").unwrap();
+        let tracks = [
+            ("a", "Discovery", "music"),
+            ("b", "Homework", "music"),
+            ("c", "Discovery", "music"),
+            ("e", "Discovery", "archive"),
+        ];
+        for (id, album, dir) in tracks {
+            let mut track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: format!("/{}/{}.mp3", dir, id),
+                ..Default::default()
+            };
+            track.album.id = album.to_lowercase();
+            track.album.name = album.to_string();
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        // Les titres d'un dossier désactivé ne sont pas retenus par les règles
+        let mut archive = Library::at(Path::new("/archive"));
+        archive.id = "archive".to_string();
+        archive.enabled = false;
+        let libraries = Libraries::new(vec![Library::at(Path::new("/music")), archive]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(libraries))
+                .service(
+                    web::scope("/playlists")
+                        .service(new_playlist)
+                        .service(get_playlist)
+                        .service(edit_playlist)
+                        .service(add_playlist_tracks),
+                ),
+        )
+        .await;
+
+        // Titres calculés depuis les règles, dans l'ordre demandé
+        let req = test::TestRequest::post()
+            .uri("/playlists")
+            .set_json(json!({
+                "name": "Discovery",
+                "rules": {
+                    "rules": [{ "field": "album", "op": "is", "value": "discovery" }],
+                    "sort": { "field": "name", "order": "desc" },
+                },
+            }))
+            .to_request();
+        let smart: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(smart["track_ids"], json!(["c", "a"]));
+        let uri = format!("/playlists/{}", smart["id"].as_str().unwrap());
+
+        // Ajouts manuels refusés
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/tracks", uri))
+            .set_json(json!({ "track_ids": ["b"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Nouvelles règles, et titres indexés ensuite repris à la lecture
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .set_json(json!({
+                "rules": { "rules": [{ "field": "album", "op": "is_not", "value": "discovery" }] },
+            }))
+            .to_request();
+        let playlist: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(playlist["track_ids"], json!(["b"]));
+
+        let mut track = Item {
+            id: "d".to_string(),
+            name: "D".to_string(),
+            path: "/music/d.mp3".to_string(),
+            ..Default::default()
+        };
+        track.album.id = "homework".to_string();
+        track.album.name = "Homework".to_string();
+        save_track(&db.connection(), &track).unwrap();
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let playlist: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(playlist["track_ids"], json!(["b", "d"]));
+
+        // Une liste ordinaire n'accepte pas de règles
+        let req = test::TestRequest::post()
+            .uri("/playlists")
+            .set_json(json!({ "name": "Road trip" }))
+            .to_request();
+        let plain: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::patch()
+            .uri(&format!("/playlists/{}", plain["id"].as_str().unwrap()))
+            .set_json(json!({ "rules": { "rules": [] } }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn exports_stay_inside_the_libraries() {
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("Discovery")).unwrap();
+        let mut track_ids = Vec::new();
+        for (id, path) in [("a", "Discovery/01.mp3"), ("b", "Homework/02.mp3")] {
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: root.join(path).to_string_lossy().into_owned(),
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+            track_ids.push(track.id);
+        }
+        let playlist = Playlist {
+            id: "mix".to_string(),
+            name: "Mix".to_string(),
+            description: String::new(),
+            cover: None,
+            source: None,
+            created_at: 0,
+            updated_at: 0,
+            rules: None,
+            track_ids,
+            tracks: Vec::new(),
+        };
+        create_playlist(&db.connection(), &playlist).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(&root)]).unwrap(),
+                ))
+                .service(web::scope("/playlists").service(export_playlist)),
+        )
+        .await;
+        let export = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        // Sans dossier demandé, chaque titre est relatif à la racine de sa bibliothèque
+        let body = test::call_and_read_body(&app, export("/playlists/mix/export")).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("\nDiscovery/01.mp3\n"));
+        assert!(body.contains("\nHomework/02.mp3\n"));
+        assert!(!body.contains(&*root.to_string_lossy()));
+
+        // Les titres hors du dossier demandé sont omis plutôt que remontés par `..`
+        let body = test::call_and_read_body(
+            &app,
+            export("/playlists/mix/export?library=test&dir=Discovery"),
+        )
+        .await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("\n01.mp3\n"));
+        assert!(!body.contains("02.mp3"));
+    }
+}
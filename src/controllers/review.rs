@@ -0,0 +1,233 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde_json::json;
+use std::path::Path;
+
+use super::errors::server_error;
+use crate::api::spotify::SpotifyClient;
+use crate::data::libraries::Libraries;
+use crate::data::models::{Item, MatchDecision, Page, ReviewMatchRequest, ReviewQuery};
+use crate::data::review::{apply_decision, fetch_spotify_track, review_items};
+use crate::database::{database::Database, library::load_track, matches::load_candidates};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+#[get("/review")]
+pub async fn get_review_queue(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    web::Query(info): web::Query<ReviewQuery>,
+) -> impl Responder {
+    let page = Page {
+        limit: Some(info.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: info.offset.unwrap_or(0),
+        ..Default::default()
+    };
+    let queue = review_items(&db.connection(), &libraries.prefixes(), &page);
+
+    match queue {
+        Ok((mut items, total)) => {
+            for item in &mut items {
+                libraries.expose_track(&mut item.track);
+            }
+            HttpResponse::Ok().json(json!({
+                "tracks": items,
+                "total": total,
+                "limit": page.limit,
+                "offset": page.offset,
+            }))
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+// Accepte un des candidats proposés lors de l'analyse
+#[post("/review/{id}/accept")]
+pub async fn accept_candidate(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Json(info): web::Json<ReviewMatchRequest>,
+) -> impl Responder {
+    let track = match find_track(&db, &libraries, &id) {
+        Ok(track) => track,
+        Err(response) => return response,
+    };
+
+    let candidate = match load_candidates(&db.connection(), &track.path) {
+        Ok(candidates) => candidates
+            .into_iter()
+            .find(|candidate| candidate.track.id == info.spotify_id),
+        Err(err) => return server_error(err),
+    };
+
+    match candidate {
+        Some(candidate) => {
+            decide(
+                &db,
+                &libraries,
+                track,
+                MatchDecision::Spotify(Box::new(candidate.track)),
+            )
+            .await
+        }
+        None => HttpResponse::BadRequest().json(json!({
+            "message": "Candidate not found"
+        })),
+    }
+}
+
+// Associe le fichier à un titre Spotify choisi librement
+#[post("/review/{id}/match")]
+pub async fn match_track(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    spotify: web::Data<SpotifyClient>,
+    id: web::Path<String>,
+    web::Json(info): web::Json<ReviewMatchRequest>,
+) -> impl Responder {
+    let track = match find_track(&db, &libraries, &id) {
+        Ok(track) => track,
+        Err(response) => return response,
+    };
+
+    match fetch_spotify_track(&spotify, &info.spotify_id).await {
+        Ok(spotify_track) => {
+            decide(
+                &db,
+                &libraries,
+                track,
+                MatchDecision::Spotify(Box::new(spotify_track)),
+            )
+            .await
+        }
+        Err(err) => {
+            println!("{}", err);
+            HttpResponse::BadGateway().json(json!({
+                "message": "Spotify track not found"
+            }))
+        }
+    }
+}
+
+// Garde le fichier comme titre local, sans le proposer de nouveau
+#[post("/review/{id}/local")]
+pub async fn keep_local(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match find_track(&db, &libraries, &id) {
+        Ok(track) => decide(&db, &libraries, track, MatchDecision::Local).await,
+        Err(response) => response,
+    }
+}
+
+// Les titres des dossiers désactivés ne sont pas révisables
+fn find_track(db: &Database, libraries: &Libraries, id: &str) -> Result<Item, HttpResponse> {
+    match load_track(&db.connection(), id) {
+        Ok(Some(track)) if libraries.containing(Path::new(&track.path)).is_some() => Ok(track),
+        Ok(_) => Err(HttpResponse::NotFound().json(json!({
+            "message": "Track not found"
+        }))),
+        Err(err) => Err(server_error(err)),
+    }
+}
+
+// Relire les tags d'un fichier bloque : la décision est appliquée hors du worker HTTP
+async fn decide(
+    db: &Database,
+    libraries: &Libraries,
+    track: Item,
+    decision: MatchDecision,
+) -> HttpResponse {
+    let db = db.clone();
+    let decided = web::block(move || apply_decision(&mut db.connection(), &track, decision)).await;
+    match decided {
+        Ok(Ok(mut track)) => {
+            libraries.expose_track(&mut track);
+            HttpResponse::Ok().json(track)
+        }
+        Ok(Err(err)) => server_error(err),
+        Err(err) => server_error(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::libraries::Library;
+    use crate::data::models::FileFingerprint;
+    use crate::database::{files::save_fingerprint, library::save_track};
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn review_queue_is_paged_and_limited_to_enabled_libraries() {
+        let db = Database::open(":memory:").unwrap();
+        for (id, dir) in [
+            ("a", "music"),
+            ("b", "music"),
+            ("c", "music"),
+            ("x", "archive"),
+        ] {
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: format!("/{}/{}.mp3", dir, id),
+                is_local: true,
+                ..Default::default()
+            };
+            let fingerprint = FileFingerprint {
+                path: track.path.clone(),
+                size: 0,
+                mtime: 0,
+                hash: id.to_string(),
+            };
+            save_fingerprint(&db.connection(), &fingerprint).unwrap();
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        let mut archive = Library::at(Path::new("/archive"));
+        archive.id = "archive".to_string();
+        archive.enabled = false;
+        let libraries = Libraries::new(vec![Library::at(Path::new("/music")), archive]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(libraries))
+                .service(get_review_queue)
+                .service(keep_local),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/review?limit=2&offset=1")
+            .to_request();
+        let queue: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(queue["total"], 3);
+        assert_eq!(queue["tracks"][0]["track"]["id"], "b");
+        assert_eq!(queue["tracks"][1]["track"]["id"], "c");
+
+        let req = test::TestRequest::get().uri("/review?limit=0").to_request();
+        let queue: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(queue["limit"], 1);
+        assert_eq!(queue["tracks"].as_array().unwrap().len(), 1);
+
+        // Décision appliquée hors du worker, refusée pour un dossier désactivé
+        let req = test::TestRequest::post()
+            .uri("/review/a/local")
+            .to_request();
+        let track: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(track["id"], "a");
+        let req = test::TestRequest::post()
+            .uri("/review/x/local")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/review").to_request();
+        let queue: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(queue["total"], 2);
+    }
+}
@@ -0,0 +1,172 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+
+use super::errors::server_error;
+use crate::data::libraries::Libraries;
+use crate::data::models::LibrarySearchQuery;
+use crate::data::search::{parse_query, SearchKind, SearchToken, SEARCH_KINDS};
+use crate::database::{
+    database::Database,
+    library::{load_album, load_artist, load_track},
+    search::{match_expression, search_ids},
+};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+// Titres, albums et artistes de la bibliothèque, groupés et triés par pertinence
+#[get("/search")]
+pub async fn search_library(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    web::Query(info): web::Query<LibrarySearchQuery>,
+) -> impl Responder {
+    let tokens = parse_query(&info.q);
+    if tokens.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "message": "Search query cannot be empty"
+        }));
+    }
+
+    let kinds = match info.kind.as_deref() {
+        None => SEARCH_KINDS.to_vec(),
+        Some(name) => match SearchKind::from_name(name) {
+            Some(kind) => vec![kind],
+            None => {
+                return HttpResponse::BadRequest().json(json!({
+                    "message": "Unknown result type",
+                    "types": SEARCH_KINDS.map(|kind| kind.name()),
+                }))
+            }
+        },
+    };
+    let limit = info.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = info.offset.unwrap_or(0);
+
+    let results = search(&db.connection(), &libraries, &tokens, &kinds, limit, offset);
+    match results {
+        Ok(groups) => {
+            let mut body = Map::new();
+            body.insert("query".to_string(), json!(info.q));
+            body.insert("limit".to_string(), json!(limit));
+            body.insert("offset".to_string(), json!(offset));
+            body.extend(groups);
+            HttpResponse::Ok().json(body)
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+fn search(
+    conn: &Connection,
+    libraries: &Libraries,
+    tokens: &[SearchToken],
+    kinds: &[SearchKind],
+    limit: usize,
+    offset: usize,
+) -> rusqlite::Result<Vec<(String, Value)>> {
+    let expression = match_expression(conn, tokens)?;
+    let prefixes = libraries.prefixes();
+    let mut groups = Vec::new();
+
+    for kind in kinds {
+        let (ids, total) = search_ids(conn, &expression, *kind, &prefixes, limit, offset)?;
+        let mut items = Vec::new();
+        for id in &ids {
+            let item = match kind {
+                SearchKind::Tracks => load_track(conn, id)?.map(|mut track| {
+                    libraries.expose_track(&mut track);
+                    json!(track)
+                }),
+                SearchKind::Albums => load_album(conn, id)?.map(|mut album| {
+                    libraries.expose_album(&mut album);
+                    json!(album)
+                }),
+                SearchKind::Artists => load_artist(conn, id)?.map(|mut artist| {
+                    libraries.expose_artist(&mut artist);
+                    json!(artist)
+                }),
+            };
+            items.extend(item);
+        }
+
+        groups.push((
+            kind.name().to_string(),
+            json!({
+                "total": total,
+                "items": items,
+            }),
+        ));
+    }
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::libraries::Library;
+    use crate::data::models::{Album, Artist, Item};
+    use crate::database::library::save_track;
+    use actix_web::{http::StatusCode, test, App};
+    use std::path::Path;
+
+    #[actix_web::test]
+    async fn search_groups_results_by_type() {
+        let db = Database::open(":memory:").unwrap();
+        let artist = Artist {
+            id: "daft-punk".to_string(),
+            name: "Daft Punk".to_string(),
+            ..Default::default()
+        };
+        let track = Item {
+            id: "get-lucky".to_string(),
+            name: "Get Lucky".to_string(),
+            path: "/music/get-lucky.mp3".to_string(),
+            artists: vec![artist.clone()],
+            album: Album {
+                id: "ram".to_string(),
+                name: "Random Access Memories".to_string(),
+                release_date: "2013-05-17".to_string(),
+                artists: vec![artist],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        save_track(&db.connection(), &track).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(
+                    Libraries::new(vec![Library::at(Path::new("/music"))]).unwrap(),
+                ))
+                .service(web::scope("/library").service(search_library)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/library/search?q=dafd%20year:2013")
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["tracks"]["total"], 1);
+        assert_eq!(body["tracks"]["items"][0]["id"], "get-lucky");
+        assert_eq!(body["tracks"]["items"][0]["path"], "get-lucky.mp3");
+        assert_eq!(body["albums"]["items"][0]["name"], "Random Access Memories");
+        assert_eq!(body["artists"]["total"], 0);
+
+        let req = test::TestRequest::get()
+            .uri("/library/search?q=lucky&type=albums")
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["albums"]["total"], 0);
+        assert!(body.get("tracks").is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/library/search?q=%20-%20")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,85 +1,650 @@
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_files::NamedFile;
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::path::Path;
 
-use crate::data::{models::TracksQuery, utils::get_tracks_data};
+use super::errors::server_error;
+use crate::data::{
+    libraries::Libraries,
+    models::{Album, ListingSort, Page, StreamQuery, TrackFilter, TracksQuery, LISTING_SORTS},
+    transcode::{Profile, Transcoded, Transcoder, PROFILES},
+    utils::audio_content_type,
+};
+use crate::database::{
+    database::Database,
+    files::record_play,
+    library::{
+        load_album, load_albums, load_artist, load_artist_albums, load_artists, load_track,
+        load_tracks,
+    },
+};
 
-#[get("/tracks")]
-pub async fn get_tracks(web::Query(info): web::Query<TracksQuery>) -> impl Responder {
-    let file_path_str = &info.path;
-    let file_path = Path::new(file_path_str);
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 500;
 
-    if let Some(result) = get_tracks_data(file_path).await {
-        match result {
-            Ok(data) => {
-                println!("YES!!! Tracks: {}", data.tracks.len());
-                HttpResponse::Ok().json(json!({
-                    "tracks": data.tracks,
-                }))
-            }
-            Err(err) => {
-                println!("{}", err);
-                let res = json!({
-                    "message": "Erreur lors de la récupération des données"
-                });
-                HttpResponse::InternalServerError().json(res)
+#[get("/{library}/tracks")]
+pub async fn get_tracks(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    library: web::Path<String>,
+    web::Query(info): web::Query<TracksQuery>,
+) -> impl Responder {
+    let (filter, page) = match listing(&libraries, &library, &info) {
+        Ok(listing) => listing,
+        Err(response) => return response,
+    };
+
+    let tracks = load_tracks(&db.connection(), &filter, &page);
+    match tracks {
+        Ok((mut tracks, total)) => {
+            tracks
+                .iter_mut()
+                .for_each(|track| libraries.expose_track(track));
+            HttpResponse::Ok().json(json!({
+                "tracks": tracks,
+                "total": total,
+                "limit": page.limit,
+                "offset": page.offset,
+            }))
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+#[get("/{library}/albums")]
+pub async fn get_albums(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    library: web::Path<String>,
+    web::Query(info): web::Query<TracksQuery>,
+) -> impl Responder {
+    let (filter, page) = match listing(&libraries, &library, &info) {
+        Ok(listing) => listing,
+        Err(response) => return response,
+    };
+
+    let albums = load_albums(&db.connection(), &filter, &page);
+    match albums {
+        Ok((mut albums, total)) => {
+            albums
+                .iter_mut()
+                .for_each(|album| libraries.expose_album(album));
+            HttpResponse::Ok().json(json!({
+                "albums": albums,
+                "total": total,
+                "limit": page.limit,
+                "offset": page.offset,
+            }))
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+#[get("/{library}/artists")]
+pub async fn get_artists(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    library: web::Path<String>,
+    web::Query(info): web::Query<TracksQuery>,
+) -> impl Responder {
+    let (filter, page) = match listing(&libraries, &library, &info) {
+        Ok(listing) => listing,
+        Err(response) => return response,
+    };
+
+    let artists = load_artists(&db.connection(), &filter, &page);
+    match artists {
+        Ok((mut artists, total)) => {
+            artists
+                .iter_mut()
+                .for_each(|artist| libraries.expose_artist(artist));
+            HttpResponse::Ok().json(json!({
+                "artists": artists,
+                "total": total,
+                "limit": page.limit,
+                "offset": page.offset,
+            }))
+        }
+        Err(err) => server_error(err),
+    }
+}
+
+#[get("/tracks/{id}")]
+pub async fn get_track(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+) -> impl Responder {
+    // Un titre hors des dossiers activés n'est pas consultable
+    let track = load_track(&db.connection(), &id)
+        .map(|track| track.filter(|track| libraries.containing(Path::new(&track.path)).is_some()));
+    match track {
+        Ok(Some(mut track)) => {
+            libraries.expose_track(&mut track);
+            HttpResponse::Ok().json(track)
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "message": "Track not found"
+        })),
+        Err(err) => server_error(err),
+    }
+}
+
+// Album avec ses titres dans l'ordre des disques et des pistes, limités aux dossiers activés
+#[get("/albums/{id}")]
+pub async fn get_album(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let album = {
+        let conn = db.connection();
+        load_album(&conn, &id).and_then(|album| match album {
+            Some(mut album) => {
+                let filter = TrackFilter {
+                    album_id: Some(album.id.clone()),
+                    ..Default::default()
+                };
+                album.items = load_tracks(&conn, &filter, &Page::default())?.0;
+                album
+                    .items
+                    .retain(|track| libraries.containing(Path::new(&track.path)).is_some());
+                Ok(Some(album).filter(|album| !album.items.is_empty()))
             }
+            None => Ok(None),
+        })
+    };
+
+    match album {
+        Ok(Some(mut album)) => {
+            libraries.expose_album(&mut album);
+            HttpResponse::Ok().json(album)
         }
-    } else {
-        HttpResponse::InternalServerError().body("Internal Server Error")
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "message": "Album not found"
+        })),
+        Err(err) => server_error(err),
     }
 }
 
-#[get("/albums")]
-pub async fn get_albums(web::Query(info): web::Query<TracksQuery>) -> impl Responder {
-    let file_path_str = &info.path;
-    let file_path = Path::new(file_path_str);
-
-    if let Some(result) = get_tracks_data(file_path).await {
-        match result {
-            Ok(data) => {
-                println!("YES!!! Albums: {}", data.albums.len());
-                HttpResponse::Ok().json(json!({
-                    "albums": data.albums,
-                }))
+// Artiste et ses albums regroupés par type : album, single, compilation
+#[get("/artists/{id}")]
+pub async fn get_artist(
+    db: web::Data<Database>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let artist = {
+        let conn = db.connection();
+        load_artist(&conn, &id).and_then(|artist| match artist {
+            Some(artist) => {
+                let albums = load_artist_albums(&conn, &id, &libraries.prefixes())?;
+                Ok(Some((artist, albums)).filter(|(_, albums)| !albums.is_empty()))
             }
-            Err(err) => {
-                println!("{}", err);
-                let res = json!({
-                    "message": "Erreur lors de la récupération des données"
-                });
-                HttpResponse::InternalServerError().json(res)
+            None => Ok(None),
+        })
+    };
+
+    match artist {
+        Ok(Some((mut artist, albums))) => {
+            libraries.expose_artist(&mut artist);
+            let mut groups: BTreeMap<String, Vec<Album>> = ["album", "single", "compilation"]
+                .into_iter()
+                .map(|album_type| (album_type.to_string(), Vec::new()))
+                .collect();
+            for mut album in albums {
+                libraries.expose_album(&mut album);
+                groups
+                    .entry(album.album_type.clone())
+                    .or_default()
+                    .push(album);
             }
+
+            HttpResponse::Ok().json(json!({
+                "artist": artist,
+                "albums": groups,
+            }))
         }
-    } else {
-        HttpResponse::InternalServerError().body("Internal Server Error")
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "message": "Artist not found"
+        })),
+        Err(err) => server_error(err),
     }
 }
 
-#[get("/artists")]
-pub async fn get_artists(web::Query(info): web::Query<TracksQuery>) -> impl Responder {
-    let file_path_str = &info.path;
-    let file_path = Path::new(file_path_str);
+// Filtres et page d'une liste, limitée aux titres du dossier demandé
+fn listing(
+    libraries: &Libraries,
+    library: &str,
+    info: &TracksQuery,
+) -> Result<(TrackFilter, Page), HttpResponse> {
+    let library = match libraries.get(library) {
+        Some(library) => library,
+        None => {
+            return Err(HttpResponse::NotFound().json(json!({
+                "message": "Library not found"
+            })))
+        }
+    };
+
+    let filter = TrackFilter {
+        path: library.prefix(),
+        artist_id: info.artist_id.clone(),
+        album_id: info.album_id.clone(),
+        format: info.format.clone(),
+        explicit: info.explicit,
+    };
+    Ok((filter, listing_page(info)?))
+}
+
+fn listing_page(info: &TracksQuery) -> Result<Page, HttpResponse> {
+    let sort = match info.sort.as_deref() {
+        None => None,
+        Some(name) => match ListingSort::from_name(name) {
+            Some(sort) => Some(sort),
+            None => {
+                return Err(HttpResponse::BadRequest().json(json!({
+                    "message": "Unknown sort",
+                    "sorts": LISTING_SORTS.map(|sort| sort.name()),
+                })))
+            }
+        },
+    };
+
+    Ok(Page {
+        limit: Some(info.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: info.offset.unwrap_or(0),
+        sort,
+        order: info.order,
+    })
+}
+
+// Fichier audio d'un titre, avec Range, ETag et Last-Modified gérés par NamedFile
+#[get("/tracks/{id}/stream")]
+pub async fn stream_track(
+    db: web::Data<Database>,
+    transcoder: web::Data<Transcoder>,
+    libraries: web::Data<Libraries>,
+    id: web::Path<String>,
+    web::Query(info): web::Query<StreamQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let track = match load_track(&db.connection(), &id) {
+        Ok(Some(track)) => track,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "message": "Track not found"
+            }))
+        }
+        Err(err) => return server_error(err),
+    };
 
-    if let Some(result) = get_tracks_data(file_path).await {
-        match result {
-            Ok(data) => {
-                println!("YES!!! Artists: {}", data.artists.len());
-                println!("YES!!! artist de lalbum: {}", data.artists[0].albums[0].artists.len());
+    // Seuls les fichiers d'un dossier activé sont servis, liens symboliques résolus ;
+    // le chemin vérifié est celui qui est ouvert ensuite
+    let Some(source) = libraries.confine(Path::new(&track.path)) else {
+        println!("{}: file not found or outside of the libraries", track.path);
+        return HttpResponse::NotFound().json(json!({
+            "message": "Audio file not found"
+        }));
+    };
 
-                HttpResponse::Ok().json(json!({
-                    "artists": data.artists,
-                }))
+    // Sans profil, le fichier original ; sinon sa version transcodée en cache
+    let (path, content_type) = match info.profile.as_deref() {
+        None => {
+            let content_type = source.extension().and_then(audio_content_type);
+            (source, content_type)
+        }
+        Some(name) => {
+            let Some(profile) = Profile::from_name(name) else {
+                return HttpResponse::BadRequest().json(json!({
+                    "message": "Unknown transcoding profile",
+                    "profiles": PROFILES.map(|profile| profile.name()),
+                }));
+            };
+
+            match transcoder.transcode(&track, &source, profile).await {
+                Ok(Transcoded::Cached(path)) => (path, Some(profile.content_type())),
+                // Premier transcodage : envoyé pendant l'encodage, sans Range
+                Ok(Transcoded::Streaming(body)) => {
+                    count_play(&db, &req, &track.path);
+                    return HttpResponse::Ok()
+                        .content_type(profile.content_type())
+                        .body(body);
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    return HttpResponse::InternalServerError().json(json!({
+                        "message": "Erreur lors du transcodage"
+                    }));
+                }
             }
-            Err(err) => {
-                println!("{}", err);
-                let res = json!({
-                    "message": "Erreur lors de la récupération des données"
-                });
-                HttpResponse::InternalServerError().json(res)
+        }
+    };
+
+    let content_type =
+        content_type.and_then(|content_type| content_type.parse::<mime::Mime>().ok());
+    match (NamedFile::open_async(&path).await, content_type) {
+        (Ok(file), Some(content_type)) => {
+            let response = file.set_content_type(content_type).into_response(&req);
+            if response.status().is_success() {
+                count_play(&db, &req, &track.path);
             }
+            response
+        }
+        (Err(err), _) => {
+            println!("{}: {}", path.display(), err);
+            HttpResponse::NotFound().json(json!({
+                "message": "Audio file not found"
+            }))
+        }
+        (_, None) => HttpResponse::UnsupportedMediaType().json(json!({
+            "message": "Unsupported audio format"
+        })),
+    }
+}
+
+// Lecture enregistrée pour les listes intelligentes
+fn count_play(db: &Database, req: &HttpRequest, path: &str) {
+    if starts_playback(req) {
+        if let Err(err) = record_play(&db.connection(), path) {
+            println!("{}", err);
+        }
+    }
+}
+
+// Une lecture est comptée au début du fichier, pas à chaque requête Range du lecteur
+fn starts_playback(req: &HttpRequest) -> bool {
+    match req.headers().get(header::RANGE) {
+        None => true,
+        Some(range) => range
+            .to_str()
+            .map(|range| range.replace(' ', "").starts_with("bytes=0-"))
+            .unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::libraries::Library;
+    use crate::data::models::{Artist, Item};
+    use crate::data::transcode::TranscodeConfig;
+    use crate::database::library::save_track;
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn stream_serves_ranges_and_conditional_requests() {
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("01.flac");
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let track = Item {
+            id: "local-track".to_string(),
+            path: path.to_string_lossy().into_owned(),
+            is_local: true,
+            ..Default::default()
+        };
+        save_track(&db.connection(), &track).unwrap();
+
+        // Lien symbolique vers un fichier hors de la bibliothèque
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.flac"), b"secret").unwrap();
+        let link = dir.path().join("02.flac");
+        std::os::unix::fs::symlink(outside.path().join("secret.flac"), &link).unwrap();
+        let escaped = Item {
+            id: "escaped-track".to_string(),
+            path: link.to_string_lossy().into_owned(),
+            is_local: true,
+            ..Default::default()
+        };
+        save_track(&db.connection(), &escaped).unwrap();
+
+        let libraries = Libraries::new(vec![Library::at(dir.path())]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(Transcoder::new(TranscodeConfig::default())))
+                .app_data(web::Data::new(libraries))
+                .service(stream_track),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/tracks/local-track/stream")
+            .insert_header((header::RANGE, "bytes=2-5"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "audio/flac"
+        );
+        assert_eq!(
+            res.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-5/10"
+        );
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+        assert_eq!(test::read_body(res).await, "2345");
+
+        let req = test::TestRequest::get()
+            .uri("/tracks/local-track/stream")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::get()
+            .uri("/tracks/local-track/stream?profile=wav-9000")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        for uri in ["/tracks/unknown/stream", "/tracks/escaped-track/stream"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn listings_are_paged_sorted_and_filtered() {
+        let db = Database::open(":memory:").unwrap();
+        let tracks = [
+            ("a", "air", "Moon Safari", "flac", 300_000, false, 2),
+            ("b", "air", "Moon Safari", "mp3", 200_000, true, 1),
+            ("c", "justice", "Cross", "flac", 400_000, true, 1),
+        ];
+        for (id, artist, album, format, duration_ms, explicit, track_number) in tracks {
+            let artist = Artist {
+                id: artist.to_string(),
+                name: artist.to_uppercase(),
+                ..Default::default()
+            };
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: format!("/music/{}.{}", id, format),
+                duration_ms,
+                explicit,
+                track_number,
+                artists: vec![artist.clone()],
+                album: Album {
+                    id: album.to_lowercase(),
+                    name: album.to_string(),
+                    album_type: "album".to_string(),
+                    artists: vec![artist],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        let libraries = Libraries::new(vec![Library::at(Path::new("/music"))]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(libraries))
+                .service(get_tracks)
+                .service(get_albums)
+                .service(get_artists),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body: Value = test::call_and_read_body_json(
+            &app,
+            get("/test/tracks?sort=duration&order=desc&limit=2&offset=1"),
+        )
+        .await;
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["tracks"][0]["id"], "a");
+        assert_eq!(body["tracks"][0]["path"], "a.flac");
+        assert_eq!(body["tracks"][0]["library"], "test");
+        assert_eq!(body["tracks"][1]["id"], "b");
+
+        let body: Value =
+            test::call_and_read_body_json(&app, get("/test/albums?format=flac&sort=name")).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["albums"][0]["name"], "Cross");
+        assert_eq!(body["albums"][1]["items"].as_array().unwrap().len(), 1);
+
+        let body: Value = test::call_and_read_body_json(
+            &app,
+            get("/test/artists?explicit=true&album_id=moon%20safari"),
+        )
+        .await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["artists"][0]["name"], "AIR");
+
+        let res = test::call_service(&app, get("/test/tracks?sort=bpm")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = test::call_service(&app, get("/other/tracks")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn lookups_order_album_tracks_and_group_artist_albums() {
+        let db = Database::open(":memory:").unwrap();
+        let air = Artist {
+            id: "air".to_string(),
+            name: "AIR".to_string(),
+            ..Default::default()
+        };
+        let tracks = [
+            ("a", "moon-safari", "Moon Safari", "album", 1, 2),
+            ("b", "moon-safari", "Moon Safari", "album", 1, 1),
+            ("c", "moon-safari", "Moon Safari", "album", 2, 1),
+            ("d", "kelly", "Kelly Watch the Stars", "single", 1, 1),
+        ];
+        for (id, album_id, album, album_type, disc_number, track_number) in tracks {
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: format!("/music/{}.flac", id),
+                disc_number,
+                track_number,
+                artists: vec![air.clone()],
+                album: Album {
+                    id: album_id.to_string(),
+                    name: album.to_string(),
+                    album_type: album_type.to_string(),
+                    artists: vec![air.clone()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        let libraries = Libraries::new(vec![Library::at(Path::new("/music"))]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(libraries))
+                .service(get_track)
+                .service(get_album)
+                .service(get_artist),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body: Value = test::call_and_read_body_json(&app, get("/tracks/c")).await;
+        assert_eq!(body["album"]["name"], "Moon Safari");
+        assert_eq!(body["path"], "c.flac");
+        assert_eq!(body["library"], "test");
+
+        // Titres dans l'ordre des disques puis des pistes
+        let body: Value = test::call_and_read_body_json(&app, get("/albums/moon-safari")).await;
+        let ids: Vec<&str> = body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|track| track["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["b", "a", "c"]);
+
+        let body: Value = test::call_and_read_body_json(&app, get("/artists/air")).await;
+        assert_eq!(body["artist"]["name"], "AIR");
+        assert_eq!(body["albums"]["album"][0]["name"], "Moon Safari");
+        assert_eq!(body["albums"]["single"][0]["name"], "Kelly Watch the Stars");
+        assert_eq!(body["albums"]["compilation"], json!([]));
+
+        for uri in ["/tracks/missing", "/albums/missing", "/artists/missing"] {
+            let res = test::call_service(&app, get(uri)).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn lookups_hide_disabled_libraries() {
+        let db = Database::open(":memory:").unwrap();
+        let artist = Artist {
+            id: "air".to_string(),
+            name: "AIR".to_string(),
+            ..Default::default()
+        };
+        for (id, dir) in [("a", "music"), ("b", "archive")] {
+            let track = Item {
+                id: id.to_string(),
+                path: format!("/{}/{}.flac", dir, id),
+                artists: vec![artist.clone()],
+                album: Album {
+                    id: dir.to_string(),
+                    name: dir.to_uppercase(),
+                    album_type: "album".to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&db.connection(), &track).unwrap();
+        }
+
+        let mut archive = Library::at(Path::new("/archive"));
+        archive.id = "archive".to_string();
+        archive.enabled = false;
+        let libraries = Libraries::new(vec![Library::at(Path::new("/music")), archive]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(libraries))
+                .service(get_track)
+                .service(get_album)
+                .service(get_artist),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body: Value = test::call_and_read_body_json(&app, get("/artists/air")).await;
+        assert_eq!(body["albums"]["album"].as_array().unwrap().len(), 1);
+        assert_eq!(body["albums"]["album"][0]["name"], "MUSIC");
+
+        for uri in ["/tracks/b", "/albums/archive"] {
+            let res = test::call_service(&app, get(uri)).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
         }
-    } else {
-        HttpResponse::InternalServerError().body("Internal Server Error")
     }
 }
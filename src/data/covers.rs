@@ -0,0 +1,185 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+// Pochettes recherchées à côté des fichiers quand aucune n'est intégrée
+const COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+const JPEG_QUALITY: u8 = 85;
+
+// Tailles de miniatures générées : une demande est arrondie à la taille supérieure,
+// le cache compte au plus une miniature par taille et par format pour chaque image
+pub const THUMBNAIL_SIZES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+pub struct Cover {
+    pub hash: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThumbnailFormat {
+    Jpeg,
+    Webp,
+}
+
+impl ThumbnailFormat {
+    pub fn from_name(name: &str) -> Option<ThumbnailFormat> {
+        match name {
+            "jpeg" | "jpg" => Some(ThumbnailFormat::Jpeg),
+            "webp" => Some(ThumbnailFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpeg",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "image/jpeg",
+            ThumbnailFormat::Webp => "image/webp",
+        }
+    }
+}
+
+// Image intégrée au fichier, sinon cover.jpg, folder.png... dans son dossier
+pub fn find_cover(file_path: &Path, embedded: Option<Vec<u8>>) -> Option<Cover> {
+    if let Some(cover) = embedded.and_then(cover_from) {
+        return Some(cover);
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(file_path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        // Fichiers ordinaires seulement : un lien pourrait mener hors de la bibliothèque
+        .filter(|path| {
+            is_cover_file(path)
+                && fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file())
+        })
+        .collect();
+    entries.sort();
+
+    entries
+        .iter()
+        .find_map(|path| fs::read(path).ok().and_then(cover_from))
+}
+
+pub fn cover_from(data: Vec<u8>) -> Option<Cover> {
+    let format = image::guess_format(&data).ok()?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+    ) {
+        return None;
+    }
+
+    let hash = Sha256::digest(&data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    Some(Cover {
+        hash,
+        mime_type: format.to_mime_type().to_string(),
+        data,
+    })
+}
+
+fn is_cover_file(path: &Path) -> bool {
+    let lowercase = |value: Option<&std::ffi::OsStr>| {
+        value
+            .map(|value| value.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+
+    COVER_NAMES.contains(&lowercase(path.file_stem()).as_str())
+        && COVER_EXTENSIONS.contains(&lowercase(path.extension()).as_str())
+}
+
+pub fn thumbnail_size(requested: u32) -> Option<u32> {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|size| requested > 0 && *size >= requested)
+}
+
+// Miniature tenant dans un carré de `size` pixels, proportions conservées
+pub fn thumbnail(data: &[u8], size: u32, format: ThumbnailFormat) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data).map_err(|err| err.to_string())?;
+    let resized = if image.width() > size || image.height() > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+    let rgb = DynamicImage::ImageRgb8(resized.to_rgb8());
+
+    let mut output = Vec::new();
+    match format {
+        ThumbnailFormat::Jpeg => {
+            rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut output, JPEG_QUALITY))
+        }
+        ThumbnailFormat::Webp => rgb.write_with_encoder(WebPEncoder::new_lossless(&mut output)),
+    }
+    .map_err(|err| err.to_string())?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn folder_images_are_used_without_embedded_art() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("01.mp3");
+        fs::write(dir.path().join("notes.png"), png(4, 4)).unwrap();
+        fs::write(dir.path().join("Folder.PNG"), png(8, 8)).unwrap();
+
+        let cover = find_cover(&track, None).unwrap();
+        assert_eq!(cover.mime_type, "image/png");
+        assert_eq!(cover.data, png(8, 8));
+
+        let embedded = find_cover(&track, Some(png(2, 2))).unwrap();
+        assert_eq!(embedded.data, png(2, 2));
+        assert!(find_cover(&track, Some(b"not an image".to_vec())).is_some());
+    }
+
+    #[test]
+    fn requested_sizes_are_rounded_up() {
+        assert_eq!(thumbnail_size(1), Some(64));
+        assert_eq!(thumbnail_size(64), Some(64));
+        assert_eq!(thumbnail_size(300), Some(512));
+        assert_eq!(thumbnail_size(1024), Some(1024));
+        assert_eq!(thumbnail_size(0), None);
+        assert_eq!(thumbnail_size(1025), None);
+    }
+
+    #[test]
+    fn thumbnails_fit_the_requested_size() {
+        let data = thumbnail(&png(400, 200), 100, ThumbnailFormat::Jpeg).unwrap();
+        let resized = image::load_from_memory(&data).unwrap();
+        assert_eq!((resized.width(), resized.height()), (100, 50));
+
+        let data = thumbnail(&png(40, 20), 100, ThumbnailFormat::Webp).unwrap();
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::WebP);
+        assert_eq!(image::load_from_memory(&data).unwrap().width(), 40);
+    }
+}
@@ -0,0 +1,311 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use super::libraries::Library;
+use super::models::{ScanProgress, ScanReport};
+use super::utils::get_tracks_data;
+use crate::api::spotify::SpotifyClient;
+use crate::database::database::Database;
+
+// Durée de conservation des analyses terminées, en secondes, et nombre maximal gardé
+const FINISHED_JOB_TTL: u64 = 3600;
+const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+// Partagé entre la tâche d'analyse et les requêtes qui la consultent ou l'annulent
+#[derive(Clone, Default)]
+pub struct ScanHandle {
+    progress: Arc<Mutex<ScanProgress>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScanHandle {
+    pub fn update(&self, f: impl FnOnce(&mut ScanProgress)) {
+        f(&mut self.progress.lock().unwrap_or_else(|err| err.into_inner()));
+    }
+
+    pub fn progress(&self) -> ScanProgress {
+        self.progress
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScanJob {
+    pub id: String,
+    pub library: String,
+    pub changed_only: bool,
+    pub status: ScanStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub progress: ScanProgress,
+    pub eta_seconds: Option<u64>,
+    pub report: Option<ScanReport>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    handle: ScanHandle,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl ScanJob {
+    fn new(library: &str, changed_only: bool) -> ScanJob {
+        ScanJob {
+            id: Uuid::new_v4().to_string(),
+            library: library.to_string(),
+            changed_only,
+            status: ScanStatus::Running,
+            started_at: now(),
+            finished_at: None,
+            progress: ScanProgress::default(),
+            eta_seconds: None,
+            report: None,
+            error: None,
+            handle: ScanHandle::default(),
+            started: Instant::now(),
+        }
+    }
+
+    // Copie de l'état courant, avec la progression et l'estimation à jour
+    fn snapshot(&self) -> ScanJob {
+        let mut job = self.clone();
+        job.progress = self.handle.progress();
+
+        if job.status == ScanStatus::Running && job.progress.seen > 0 {
+            let remaining = job.progress.total.saturating_sub(job.progress.seen) as u64;
+            let elapsed = self.started.elapsed().as_secs();
+            job.eta_seconds = Some(elapsed * remaining / job.progress.seen as u64);
+        }
+
+        job
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ScanJobs {
+    jobs: Arc<Mutex<HashMap<String, ScanJob>>>,
+    // Dossiers dont l'index est en cours d'écriture, par une analyse ou par la surveillance
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+// Accès exclusif à l'index d'un dossier, rendu quand la réservation est libérée
+pub struct Reservation {
+    library: String,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.busy
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&self.library);
+    }
+}
+
+impl ScanJobs {
+    pub fn start(
+        &self,
+        db: Database,
+        spotify: SpotifyClient,
+        library: Library,
+        changed_only: bool,
+    ) -> Result<ScanJob, String> {
+        let Some(reservation) = self.reserve(&library.id) else {
+            return Err(format!("{} is already being scanned", library.name));
+        };
+        let job = ScanJob::new(&library.id, changed_only);
+        let handle = job.handle.clone();
+        self.lock().insert(job.id.clone(), job.clone());
+
+        let jobs = self.clone();
+        let id = job.id.clone();
+        // Seuls les appels Spotify s'exécutent sur le worker, le reste passe par le pool bloquant
+        actix_web::rt::spawn(async move {
+            let result = get_tracks_data(&db, &spotify, &library, changed_only, &handle).await;
+            jobs.finish(&id, result);
+            drop(reservation);
+        });
+
+        Ok(job)
+    }
+
+    // None tant qu'une analyse ou une mise à jour du dossier est en cours
+    pub fn reserve(&self, library: &str) -> Option<Reservation> {
+        let mut busy = self.busy.lock().unwrap_or_else(|err| err.into_inner());
+        if !busy.insert(library.to_string()) {
+            return None;
+        }
+
+        Some(Reservation {
+            library: library.to_string(),
+            busy: self.busy.clone(),
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<ScanJob> {
+        self.lock().get(id).map(ScanJob::snapshot)
+    }
+
+    pub fn list(&self) -> Vec<ScanJob> {
+        let mut jobs: Vec<ScanJob> = self.lock().values().map(ScanJob::snapshot).collect();
+        jobs.sort_by_key(|job| Reverse(job.started_at));
+        jobs
+    }
+
+    pub fn cancel(&self, id: &str) -> Option<ScanJob> {
+        let jobs = self.lock();
+        let job = jobs.get(id)?;
+        job.handle.cancel();
+        Some(job.snapshot())
+    }
+
+    fn finish(&self, id: &str, result: Result<ScanReport, String>) {
+        let mut jobs = self.lock();
+        if let Some(job) = jobs.get_mut(id) {
+            job.finished_at = Some(now());
+            match result {
+                Ok(report) => {
+                    job.status = ScanStatus::Completed;
+                    job.report = Some(report);
+                }
+                Err(_) if job.handle.is_cancelled() => job.status = ScanStatus::Cancelled,
+                Err(err) => {
+                    eprintln!("Scan of {} failed: {}", job.library, err);
+                    job.status = ScanStatus::Failed;
+                    job.error = Some(err);
+                }
+            }
+        }
+    }
+
+    // Les analyses terminées restent consultables un temps, puis sont oubliées
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, ScanJob>> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        evict_finished(&mut jobs, now());
+        jobs
+    }
+}
+
+fn evict_finished(jobs: &mut HashMap<String, ScanJob>, now: u64) {
+    jobs.retain(|_, job| {
+        job.finished_at
+            .is_none_or(|finished_at| finished_at + FINISHED_JOB_TTL > now)
+    });
+
+    let mut finished: Vec<(u64, String)> = jobs
+        .values()
+        .filter_map(|job| {
+            job.finished_at
+                .map(|finished_at| (finished_at, job.id.clone()))
+        })
+        .collect();
+    if finished.len() > MAX_FINISHED_JOBS {
+        finished.sort();
+        for (_, id) in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+            jobs.remove(id);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn eta_follows_progress() {
+        let mut job = ScanJob::new("music", false);
+        assert_eq!(job.snapshot().eta_seconds, None);
+
+        job.started = Instant::now() - Duration::from_secs(10);
+        job.handle.update(|progress| {
+            progress.total = 30;
+            progress.seen = 10;
+        });
+        let snapshot = job.snapshot();
+        assert_eq!(snapshot.progress.seen, 10);
+        assert_eq!(snapshot.eta_seconds, Some(20));
+
+        job.status = ScanStatus::Completed;
+        assert_eq!(job.snapshot().eta_seconds, None);
+    }
+
+    #[test]
+    fn finished_jobs_are_evicted() {
+        let mut jobs = HashMap::new();
+        let running = ScanJob::new("music", false);
+        jobs.insert(running.id.clone(), running.clone());
+        for finished_at in 0..MAX_FINISHED_JOBS as u64 + 5 {
+            let mut job = ScanJob::new("music", false);
+            job.status = ScanStatus::Completed;
+            job.finished_at = Some(10_000 + finished_at);
+            jobs.insert(job.id.clone(), job);
+        }
+
+        evict_finished(&mut jobs, 10_000 + MAX_FINISHED_JOBS as u64);
+        assert_eq!(jobs.len(), MAX_FINISHED_JOBS + 1);
+        assert!(jobs
+            .values()
+            .filter_map(|job| job.finished_at)
+            .all(|finished_at| finished_at >= 10_005));
+
+        evict_finished(&mut jobs, 10_000 + FINISHED_JOB_TTL * 2);
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs.contains_key(&running.id));
+    }
+
+    #[test]
+    fn failed_jobs_keep_their_error() {
+        let jobs = ScanJobs::default();
+        let job = ScanJob::new("music", false);
+        jobs.lock().insert(job.id.clone(), job.clone());
+
+        jobs.finish(&job.id, Err("database is locked".to_string()));
+        let failed = jobs.get(&job.id).unwrap();
+        assert_eq!(failed.status, ScanStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("database is locked"));
+        assert!(failed.finished_at.is_some());
+    }
+
+    #[test]
+    fn libraries_are_reserved_one_writer_at_a_time() {
+        let jobs = ScanJobs::default();
+        let reservation = jobs.reserve("music").unwrap();
+        assert!(jobs.reserve("music").is_none());
+        assert!(jobs.reserve("podcasts").is_some());
+
+        drop(reservation);
+        assert!(jobs.reserve("music").is_some());
+    }
+}
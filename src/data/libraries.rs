@@ -0,0 +1,311 @@
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use super::models::{Album, Artist, Item, Playlist};
+
+// `**` traverse les dossiers, `*` reste dans un seul
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+// Dossier de musique déclaré par le serveur ; les motifs s'appliquent aux chemins relatifs au dossier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Library {
+    pub id: String,
+    pub name: String,
+    // Jamais renvoyé aux clients
+    #[serde(skip_serializing)]
+    pub path: PathBuf,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(skip)]
+    include_patterns: Vec<Pattern>,
+    #[serde(skip)]
+    exclude_patterns: Vec<Pattern>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Library {
+    #[cfg(test)]
+    pub fn at(path: &Path) -> Library {
+        Library {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            enabled: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+        }
+    }
+
+    // Sans motif d'inclusion, tous les fichiers du dossier sont retenus
+    pub fn contains(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.path) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        let matches = |pattern: &Pattern| pattern.matches_path_with(relative, GLOB_OPTIONS);
+
+        (self.include_patterns.is_empty() || self.include_patterns.iter().any(matches))
+            && !self.exclude_patterns.iter().any(matches)
+    }
+
+    // Préfixe des chemins indexés pour ce dossier
+    pub fn prefix(&self) -> String {
+        self.path.join("").to_string_lossy().into_owned()
+    }
+
+    // Chemin canonique d'un fichier existant, refusé si un lien symbolique mène hors du dossier
+    pub fn confine(&self, path: &Path) -> io::Result<PathBuf> {
+        let canonical = path.canonicalize()?;
+        if canonical.starts_with(&self.path) {
+            Ok(canonical)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Outside of the library",
+            ))
+        }
+    }
+
+    // Chemin reçu d'un client : relatif au dossier, sans `..`
+    pub fn resolve(&self, relative: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(relative);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Expected a path relative to the library",
+            ));
+        }
+
+        self.confine(&self.path.join(relative))
+    }
+
+    // Seule forme des chemins renvoyée aux clients
+    pub fn relative(&self, path: &str) -> Option<String> {
+        Path::new(path)
+            .strip_prefix(&self.path)
+            .ok()
+            .map(|relative| relative.to_string_lossy().into_owned())
+    }
+
+    fn compile(&mut self) -> Result<(), String> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern)
+                        .map_err(|err| format!("Library {}: {}: {}", self.id, pattern, err))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        self.include_patterns = compile(&self.include)?;
+        self.exclude_patterns = compile(&self.exclude)?;
+
+        Ok(())
+    }
+}
+
+// Validés dès la lecture des réglages
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "Vec<Library>")]
+pub struct Libraries {
+    libraries: Vec<Library>,
+}
+
+impl TryFrom<Vec<Library>> for Libraries {
+    type Error = String;
+
+    fn try_from(libraries: Vec<Library>) -> Result<Libraries, String> {
+        Libraries::new(libraries)
+    }
+}
+
+impl Libraries {
+    pub fn new(mut libraries: Vec<Library>) -> Result<Libraries, String> {
+        let mut ids = HashSet::new();
+        for library in &mut libraries {
+            if library.id.is_empty() || !ids.insert(library.id.clone()) {
+                return Err(format!("Invalid or duplicate library id: {:?}", library.id));
+            }
+            library.compile()?;
+
+            // Racine canonique : les chemins indexés et les vérifications s'y comparent.
+            // Un dossier introuvable est signalé par la validation des réglages.
+            if let Ok(path) = library.path.canonicalize() {
+                library.path = path;
+            }
+        }
+
+        Ok(Libraries { libraries })
+    }
+
+    pub fn list(&self) -> &[Library] {
+        &self.libraries
+    }
+
+    // Les dossiers désactivés restent listés mais ne sont ni analysés ni consultables
+    pub fn get(&self, id: &str) -> Option<&Library> {
+        self.enabled().find(|library| library.id == id)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &Library> {
+        self.libraries.iter().filter(|library| library.enabled)
+    }
+
+    // Préfixes des dossiers activés, pour limiter les requêtes à ce qui est consultable
+    pub fn prefixes(&self) -> Vec<String> {
+        self.enabled().map(Library::prefix).collect()
+    }
+
+    // Chemin relatif au dossier qui le contient, None hors des dossiers activés
+    pub fn relative(&self, path: &str) -> Option<String> {
+        self.containing(Path::new(path))
+            .and_then(|library| library.relative(path))
+    }
+
+    // Dossier le plus précis contenant le chemin
+    pub fn containing(&self, path: &Path) -> Option<&Library> {
+        self.enabled()
+            .filter(|library| path.starts_with(&library.path))
+            .max_by_key(|library| library.path.components().count())
+    }
+
+    // Fichier indexé encore présent dans un dossier activé
+    pub fn confine(&self, path: &Path) -> Option<PathBuf> {
+        self.containing(path)?.confine(path).ok()
+    }
+
+    // Les chemins absolus du serveur sont remplacés par le dossier et le chemin relatif
+    pub fn expose_track(&self, track: &mut Item) {
+        let library = self.containing(Path::new(&track.path));
+        track.path = library
+            .and_then(|library| library.relative(&track.path))
+            .unwrap_or_default();
+        track.library = library.map(|library| library.id.clone());
+    }
+
+    pub fn expose_album(&self, album: &mut Album) {
+        for track in &mut album.items {
+            self.expose_track(track);
+        }
+    }
+
+    pub fn expose_artist(&self, artist: &mut Artist) {
+        for album in &mut artist.albums {
+            self.expose_album(album);
+        }
+    }
+
+    pub fn expose_playlist(&self, playlist: &mut Playlist) {
+        for track in &mut playlist.tracks {
+            self.expose_track(track);
+        }
+        playlist.source = playlist.source.as_ref().and_then(|source| {
+            self.containing(Path::new(source))
+                .and_then(|library| library.relative(source))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn globs_select_files_inside_the_library() {
+        let libraries: Vec<Library> = serde_json::from_str(
+            r#"[
+                {"id": "music", "name": "Music", "path": "/music",
+                 "include": ["**/*.flac", "**/*.mp3"], "exclude": ["Podcasts/**"]},
+                {"id": "old", "name": "Old", "path": "/old", "enabled": false}
+            ]"#,
+        )
+        .unwrap();
+        let libraries = Libraries::new(libraries).unwrap();
+
+        let music = libraries.get("music").unwrap();
+        assert!(music.contains(Path::new("/music/Air/Moon Safari/01.flac")));
+        assert!(music.contains(Path::new("/music/01.mp3")));
+        assert!(!music.contains(Path::new("/music/Air/01.wav")));
+        assert!(!music.contains(Path::new("/music/Podcasts/Show/01.mp3")));
+        assert!(!music.contains(Path::new("/musical/01.mp3")));
+
+        assert!(libraries.get("old").is_none());
+        assert!(libraries.containing(Path::new("/old/01.mp3")).is_none());
+        assert_eq!(libraries.list().len(), 2);
+
+        let duplicate = vec![Library::at(Path::new("/a")), Library::at(Path::new("/b"))];
+        assert!(Libraries::new(duplicate).is_err());
+    }
+
+    #[test]
+    fn paths_from_clients_stay_inside_the_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        fs::create_dir(dir.path().join("Playlists")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("escape")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("Playlists"), dir.path().join("inside"))
+            .unwrap();
+
+        assert_eq!(
+            library.resolve("Playlists").unwrap(),
+            library.path.join("Playlists")
+        );
+        assert_eq!(
+            library.resolve("./inside").unwrap(),
+            library.path.join("Playlists")
+        );
+
+        let absolute = outside.path().to_string_lossy().into_owned();
+        for input in [
+            "..",
+            "Playlists/../..",
+            "Playlists/../../etc",
+            &absolute,
+            "/etc",
+        ] {
+            let err = library.resolve(input).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", input);
+        }
+        let err = library.resolve("escape").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(library.resolve("missing").is_err());
+
+        let mut track = Item {
+            path: library
+                .path
+                .join("Air/01.flac")
+                .to_string_lossy()
+                .into_owned(),
+            ..Default::default()
+        };
+        Libraries::new(vec![library.clone()])
+            .unwrap()
+            .expose_track(&mut track);
+        assert_eq!(
+            (track.path.as_str(), track.library.as_deref()),
+            ("Air/01.flac", Some("test"))
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::models::{Item, MatchCandidate};
+use super::tags::TrackTags;
+
+// En dessous de ce score, la correspondance est laissée à la revue manuelle
+pub const MATCH_THRESHOLD: f64 = 0.8;
+
+const TITLE_WEIGHT: f64 = 0.45;
+const ARTIST_WEIGHT: f64 = 0.35;
+const ALBUM_WEIGHT: f64 = 0.1;
+const DURATION_WEIGHT: f64 = 0.1;
+
+const FEATURING: [&str; 4] = ["feat.", "feat ", "ft.", "featuring "];
+const ARTIST_SEPARATORS: [&str; 10] = [
+    " feat. ",
+    " feat ",
+    " ft. ",
+    " featuring ",
+    " & ",
+    ",",
+    ";",
+    "/",
+    " x ",
+    " vs. ",
+];
+
+// Candidats Spotify triés du plus au moins probable
+pub fn score_candidates(tags: &TrackTags, candidates: &[Item]) -> Vec<MatchCandidate> {
+    let mut scored: Vec<MatchCandidate> = candidates
+        .iter()
+        .map(|track| MatchCandidate {
+            score: score(tags, track),
+            track: track.clone(),
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored
+}
+
+pub fn score(tags: &TrackTags, track: &Item) -> f64 {
+    let mut total = 0.0;
+    let mut weights = 0.0;
+
+    total += TITLE_WEIGHT * similarity(&normalize(&tags.title), &normalize(&track.name));
+    weights += TITLE_WEIGHT;
+
+    total += ARTIST_WEIGHT * artist_score(&tags.artist, track);
+    weights += ARTIST_WEIGHT;
+
+    // L'album et la durée ne comptent que s'ils sont connus côté fichier
+    if !tags.album.is_empty() {
+        total += ALBUM_WEIGHT * similarity(&normalize(&tags.album), &normalize(&track.album.name));
+        weights += ALBUM_WEIGHT;
+    }
+
+    if tags.duration_ms > 0 && track.duration_ms > 0 {
+        total += DURATION_WEIGHT * duration_score(tags.duration_ms, track.duration_ms);
+        weights += DURATION_WEIGHT;
+    }
+
+    total / weights
+}
+
+// Titre ou nom sans accents, ponctuation ni artistes invités
+pub fn normalize(value: &str) -> String {
+    let folded: String = value
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    strip_featuring(&folded.replace('&', " and "))
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn artist_score(tag_artist: &str, track: &Item) -> f64 {
+    let folded: String = tag_artist.to_lowercase();
+    let tag_names: Vec<String> = split_artists(&folded)
+        .iter()
+        .map(|name| normalize(name))
+        .filter(|name| !name.is_empty())
+        .collect();
+    let track_names: Vec<String> = track.artists.iter().map(|a| normalize(&a.name)).collect();
+
+    if tag_names.is_empty() || track_names.is_empty() {
+        return 0.0;
+    }
+
+    // Nom complet (« Earth, Wind & Fire ») ou artiste principal d'un duo
+    let full = normalize(tag_artist);
+    let joined = track_names.join(" ");
+    let best_full = track_names
+        .iter()
+        .map(|name| similarity(&full, name))
+        .fold(similarity(&full, &joined), f64::max);
+    let best_primary = track_names
+        .iter()
+        .map(|name| similarity(&tag_names[0], name))
+        .fold(0.0, f64::max);
+
+    best_full.max(best_primary)
+}
+
+fn split_artists(value: &str) -> Vec<&str> {
+    let mut names = vec![value];
+
+    for separator in ARTIST_SEPARATORS {
+        names = names
+            .into_iter()
+            .flat_map(|name| name.split(separator))
+            .collect();
+    }
+
+    names.into_iter().map(str::trim).collect()
+}
+
+// Retire « (feat. X) », « [ft. X] », « (with X) » et « Titre feat. X »
+fn strip_featuring(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find(['(', '[']) {
+        let close = if rest[start..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let Some(length) = rest[start..].find(close) else {
+            break;
+        };
+
+        let inner = rest[start + 1..start + length].trim_start();
+        result.push_str(&rest[..start]);
+        if !FEATURING.iter().any(|marker| inner.starts_with(marker)) && !inner.starts_with("with ")
+        {
+            result.push_str(&rest[start..=start + length]);
+        }
+        rest = &rest[start + length + 1..];
+    }
+    result.push_str(rest);
+
+    for marker in FEATURING {
+        if let Some(index) = result.find(&format!(" {}", marker)) {
+            result.truncate(index);
+        }
+    }
+
+    result
+}
+
+// Pleine confiance jusqu'à 2 secondes d'écart, aucune au-delà de 15
+fn duration_score(file_ms: i64, track_ms: i64) -> f64 {
+    let difference = (file_ms - track_ms).abs() as f64 / 1000.0;
+    (1.0 - (difference - 2.0).max(0.0) / 13.0).clamp(0.0, 1.0)
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    let distance = levenshtein(&a_chars, &b_chars) as f64;
+    let edit = 1.0 - distance / a_chars.len().max(b_chars.len()) as f64;
+
+    let a_words: Vec<&str> = a.split(' ').collect();
+    let b_words: Vec<&str> = b.split(' ').collect();
+    let common = a_words.iter().filter(|word| b_words.contains(word)).count() as f64;
+    let jaccard = common / (a_words.len() + b_words.len()) as f64 * 2.0;
+
+    edit.max(jaccard)
+}
+
+pub fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::models::{Album, Artist};
+
+    fn track(name: &str, artists: &[&str], album: &str, duration_ms: i64) -> Item {
+        Item {
+            name: name.to_string(),
+            artists: artists
+                .iter()
+                .map(|name| Artist {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            album: Album {
+                name: album.to_string(),
+                ..Default::default()
+            },
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    fn tags(title: &str, artist: &str, album: &str, duration_ms: i64) -> TrackTags {
+        TrackTags {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalize_strips_accents_punctuation_and_featuring() {
+        assert_eq!(normalize("Déjà Vu (feat. Beyoncé)"), "deja vu");
+        assert_eq!(normalize("Get Lucky [ft. Pharrell Williams]"), "get lucky");
+        assert_eq!(normalize("Stay With Me feat. Someone"), "stay with me");
+        assert_eq!(
+            normalize("Harder, Better, Faster, Stronger"),
+            "harder better faster stronger"
+        );
+        assert_eq!(normalize("Rock & Roll (Live)"), "rock and roll live");
+    }
+
+    #[test]
+    fn featured_artists_do_not_lower_the_score() {
+        let file = tags(
+            "Get Lucky (feat. Pharrell)",
+            "Daft Punk feat. Pharrell Williams",
+            "",
+            0,
+        );
+        let candidate = track(
+            "Get Lucky",
+            &["Daft Punk", "Pharrell Williams"],
+            "Random Access Memories",
+            0,
+        );
+
+        assert!(score(&file, &candidate) > 0.99);
+    }
+
+    #[test]
+    fn remixes_and_other_durations_rank_below_the_original() {
+        let file = tags("One More Time", "Daft Punk", "Discovery", 320_000);
+        let candidates = vec![
+            track(
+                "One More Time - Romanthony's Unplugged",
+                &["Daft Punk"],
+                "Daft Club",
+                212_000,
+            ),
+            track("One More Time", &["Daft Punk"], "Discovery", 320_357),
+        ];
+
+        let scored = score_candidates(&file, &candidates);
+        assert_eq!(scored[0].track.album.name, "Discovery");
+        assert!(scored[0].score >= MATCH_THRESHOLD);
+        assert!(scored[1].score < MATCH_THRESHOLD);
+    }
+
+    #[test]
+    fn unrelated_tracks_stay_below_threshold() {
+        let file = tags(
+            "Clair de lune",
+            "Claude Debussy",
+            "Suite bergamasque",
+            300_000,
+        );
+        let candidate = track(
+            "Moonlight Sonata",
+            &["Ludwig van Beethoven"],
+            "Sonatas",
+            360_000,
+        );
+
+        assert!(score(&file, &candidate) < MATCH_THRESHOLD);
+    }
+}
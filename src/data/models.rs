@@ -4,7 +4,96 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct TracksQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub artist_id: Option<String>,
+    pub album_id: Option<String>,
+    pub format: Option<String>,
+    pub explicit: Option<bool>,
+}
+
+// Filtres des listes de titres ; un album ou un artiste est gardé si un de ses titres correspond
+#[derive(Debug, Default, Clone)]
+pub struct TrackFilter {
     pub path: String,
+    pub artist_id: Option<String>,
+    pub album_id: Option<String>,
+    pub format: Option<String>,
+    pub explicit: Option<bool>,
+}
+
+// Page d'une liste : tout est renvoyé sans limite
+#[derive(Debug, Default, Clone)]
+pub struct Page {
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub sort: Option<ListingSort>,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListingSort {
+    Name,
+    Artist,
+    Added,
+    ReleaseDate,
+    Duration,
+}
+
+pub const LISTING_SORTS: [ListingSort; 5] = [
+    ListingSort::Name,
+    ListingSort::Artist,
+    ListingSort::Added,
+    ListingSort::ReleaseDate,
+    ListingSort::Duration,
+];
+
+impl ListingSort {
+    pub fn from_name(name: &str) -> Option<ListingSort> {
+        LISTING_SORTS.into_iter().find(|sort| sort.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListingSort::Name => "name",
+            ListingSort::Artist => "artist",
+            ListingSort::Added => "added",
+            ListingSort::ReleaseDate => "release_date",
+            ListingSort::Duration => "duration",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    pub profile: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CoverQuery {
+    pub size: Option<u32>,
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LibrarySearchQuery {
+    pub q: String,
+    // tracks, albums ou artists, tous les groupes si absent
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ScanRequest {
+    // Identifiant d'un dossier configuré
+    pub library: String,
+    #[serde(default)]
+    pub changed_only: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,11 +103,298 @@ pub struct TokenData {
     pub expires_in: i64,
 }
 
+// Listes de lecture
 #[derive(Debug, Serialize)]
-pub struct Data {
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub cover: Option<String>,
+    // Fichier M3U ou XSPF de la bibliothèque dont la liste est importée
+    pub source: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    // Liste intelligente : titres recalculés à chaque lecture à partir des règles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rules: Option<SmartRules>,
+    pub track_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tracks: Vec<Item>,
-    pub albums: Vec<Album>,
-    pub artists: Vec<Artist>,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub cover: Option<String>,
+    pub rules: Option<SmartRules>,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub cover: Option<String>,
+    pub rules: Option<SmartRules>,
+}
+
+// Règles d'une liste intelligente, combinées avec ET (`all`) ou OU (`any`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartRules {
+    #[serde(rename = "match", default)]
+    pub combinator: Combinator,
+    pub rules: Vec<Rule>,
+    pub sort: Option<SmartSort>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Combinator {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Rule {
+    Group {
+        #[serde(rename = "match", default)]
+        combinator: Combinator,
+        rules: Vec<Rule>,
+    },
+    Condition(Condition),
+}
+
+// Champ comparé, puis opérateur et valeur : {"field": "popularity", "op": "gt", "value": 50}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum Condition {
+    Artist(TextRule),
+    Album(TextRule),
+    ReleaseDate(DateRule),
+    DurationMs(NumberRule),
+    Explicit(FlagRule),
+    Popularity(NumberRule),
+    Format(FormatRule),
+    PlayCount(NumberRule),
+    Added(DateRule),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum TextRule {
+    Is(String),
+    IsNot(String),
+    Contains(String),
+    NotContains(String),
+    StartsWith(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum NumberRule {
+    Is(i64),
+    IsNot(i64),
+    Gt(i64),
+    Lt(i64),
+}
+
+// Dates au format AAAA, AAAA-MM ou AAAA-MM-JJ, durées en jours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum DateRule {
+    Is(String),
+    Before(String),
+    After(String),
+    InLast(u32),
+    NotInLast(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum FlagRule {
+    Is(bool),
+}
+
+// Extension du fichier : flac, mp3...
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum FormatRule {
+    Is(String),
+    IsNot(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartSort {
+    pub field: SortField,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Name,
+    Artist,
+    Album,
+    ReleaseDate,
+    DurationMs,
+    Popularity,
+    PlayCount,
+    Added,
+    Random,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistTracksRequest {
+    pub track_ids: Vec<String>,
+    // Ajout en fin de liste si absent
+    pub position: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistMoveRequest {
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistExportQuery {
+    pub format: Option<String>,
+    // Dossier de référence des chemins relatifs, dans le dossier `library`
+    pub library: Option<String>,
+    pub dir: Option<String>,
+}
+
+// Structures de la bibliothèque
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileFingerprint {
+    pub path: String,
+    pub size: i64,
+    pub mtime: i64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum FailureReason {
+    NoTags,
+    RemoteError,
+    ReadError,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileFailure {
+    pub path: String,
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScanReport {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    pub needs_review: usize,
+    pub failures: Vec<FileFailure>,
+    pub playlists: Vec<PlaylistImport>,
+}
+
+// Compteurs pour toute la base ou pour les titres d'un dossier
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LibraryStats {
+    pub tracks: usize,
+    pub local_tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    pub to_review: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaylistImport {
+    pub path: String,
+    pub playlist_id: String,
+    pub tracks: usize,
+    pub unresolved: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchCandidate {
+    pub score: f64,
+    pub track: Item,
+}
+
+// Façon dont un fichier a été associé à son titre Spotify
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMethod {
+    Isrc,
+    Search,
+    Manual,
+}
+
+impl MatchMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchMethod::Isrc => "isrc",
+            MatchMethod::Search => "search",
+            MatchMethod::Manual => "manual",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MatchMethod> {
+        match name {
+            "isrc" => Some(MatchMethod::Isrc),
+            "search" => Some(MatchMethod::Search),
+            "manual" => Some(MatchMethod::Manual),
+            _ => None,
+        }
+    }
+}
+
+// Choix manuel pour un fichier, respecté par les analyses suivantes
+#[derive(Debug, Clone)]
+pub enum MatchDecision {
+    Spotify(Box<Item>),
+    Local,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReviewItem {
+    pub track: Item,
+    pub candidates: Vec<MatchCandidate>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewMatchRequest {
+    pub spotify_id: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScanProgress {
+    pub total: usize,
+    pub seen: usize,
+    pub matched: usize,
+    pub unmatched: usize,
+    pub errors: usize,
 }
 
 // Structures Spotify
@@ -98,8 +474,21 @@ pub struct Item {
     pub uri: String,
     #[serde(rename = "is_local")]
     pub is_local: bool,
+    // Relatif au dossier `library` dans les réponses de l'API
     #[serde(skip_deserializing)]
     pub path: String,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    // Correspondance Spotify, `id` identifiant le fichier dans l'index
+    #[serde(skip_deserializing)]
+    #[serde(rename = "spotify_id")]
+    pub spotify_id: Option<String>,
+    #[serde(skip_deserializing)]
+    #[serde(rename = "match_confidence")]
+    pub match_confidence: Option<f64>,
+    #[serde(skip_deserializing)]
+    #[serde(rename = "match_method")]
+    pub match_method: Option<MatchMethod>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -138,6 +527,11 @@ pub struct Image {
     pub url: String,
     pub height: i64,
     pub width: i64,
+    // URL Spotify d'origine, quand `url` pointe vers la copie locale
+    #[serde(skip_deserializing)]
+    #[serde(rename = "fallback_url")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_url: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,383 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::models::{Item, Playlist};
+
+// Caractères à encoder dans une URI XSPF, le séparateur `/` reste lisible
+const URI_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaylistFormat {
+    M3u8,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_name(name: &str) -> Option<PlaylistFormat> {
+        match name.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u8),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "m3u8",
+            PlaylistFormat::Xspf => "xspf",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "audio/x-mpegurl; charset=utf-8",
+            PlaylistFormat::Xspf => "application/xspf+xml",
+        }
+    }
+}
+
+pub fn is_playlist_file(extension: &OsStr) -> bool {
+    PlaylistFormat::from_name(&extension.to_string_lossy()).is_some()
+}
+
+// Contenu d'un fichier de liste : titre éventuel et entrées telles qu'écrites
+pub struct PlaylistFile {
+    pub title: Option<String>,
+    pub entries: Vec<String>,
+    format: PlaylistFormat,
+}
+
+pub fn read_playlist_file(path: &Path) -> Result<PlaylistFile, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let content = String::from_utf8_lossy(&bytes);
+    let content = content.trim_start_matches('\u{feff}');
+
+    match path
+        .extension()
+        .and_then(|extension| PlaylistFormat::from_name(&extension.to_string_lossy()))
+    {
+        Some(PlaylistFormat::Xspf) => parse_xspf(content),
+        _ => Ok(parse_m3u(content)),
+    }
+}
+
+fn parse_m3u(content: &str) -> PlaylistFile {
+    let mut title = None;
+    let mut entries = Vec::new();
+
+    for line in content.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            title = Some(name.trim().to_string());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entries.push(line.to_string());
+        }
+    }
+
+    PlaylistFile {
+        title,
+        entries,
+        format: PlaylistFormat::M3u8,
+    }
+}
+
+fn parse_xspf(content: &str) -> Result<PlaylistFile, String> {
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+
+    let mut title = None;
+    let mut entries = Vec::new();
+    let mut elements: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        match reader.read_event().map_err(|err| err.to_string())? {
+            Event::Start(element) => elements.push(element.local_name().as_ref().to_vec()),
+            Event::End(_) => {
+                elements.pop();
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|err| err.to_string())?;
+                let path: Vec<&[u8]> = elements.iter().map(Vec::as_slice).collect();
+                match path.as_slice() {
+                    [b"playlist", b"title"] => title = Some(text.trim().to_string()),
+                    [b"playlist", b"trackList", b"track", b"location"] => {
+                        entries.push(text.trim().to_string())
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(PlaylistFile {
+        title,
+        entries,
+        format: PlaylistFormat::Xspf,
+    })
+}
+
+// Chemin absolu d'une entrée, relative au dossier de la liste ; les URL distantes sont ignorées
+pub fn resolve_entry(file: &PlaylistFile, base_dir: &Path, entry: &str) -> Option<PathBuf> {
+    let path = if let Some(uri_path) = entry.strip_prefix("file://") {
+        percent_decode_str(uri_path)
+            .decode_utf8_lossy()
+            .into_owned()
+    } else if entry.contains("://") {
+        return None;
+    } else if file.format == PlaylistFormat::Xspf {
+        percent_decode_str(entry).decode_utf8_lossy().into_owned()
+    } else {
+        // Listes écrites sous Windows
+        entry.replace('\\', "/")
+    };
+
+    Some(normalize_path(&base_dir.join(path)))
+}
+
+// Retire `.` et `..` sans toucher au disque
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+pub fn relative_path(base_dir: &Path, target: &Path) -> PathBuf {
+    let base: Vec<Component> = base_dir.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+
+    relative
+}
+
+// Chemin d'un titre sous le dossier de la liste, None s'il est en dehors
+pub fn path_under(base_dir: &Path, track: &Item) -> Option<PathBuf> {
+    Path::new(&track.path)
+        .strip_prefix(base_dir)
+        .ok()
+        .map(Path::to_path_buf)
+}
+
+// `location` donne le chemin écrit pour chaque titre ; ceux sans chemin sont omis
+pub fn write_playlist(
+    playlist: &Playlist,
+    format: PlaylistFormat,
+    location: impl Fn(&Item) -> Option<PathBuf>,
+) -> String {
+    let tracks: Vec<(&Item, PathBuf)> = playlist
+        .tracks
+        .iter()
+        .filter_map(|track| location(track).map(|path| (track, path)))
+        .collect();
+
+    match format {
+        PlaylistFormat::M3u8 => write_m3u8(playlist, &tracks),
+        PlaylistFormat::Xspf => write_xspf(playlist, &tracks),
+    }
+}
+
+fn write_m3u8(playlist: &Playlist, tracks: &[(&Item, PathBuf)]) -> String {
+    let mut output = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.name);
+
+    for (track, location) in tracks {
+        let seconds = if track.duration_ms > 0 {
+            (track.duration_ms + 500) / 1000
+        } else {
+            -1
+        };
+        output.push_str(&format!(
+            "#EXTINF:{},{} - {}\n{}\n",
+            seconds,
+            artist_names(track),
+            track.name,
+            location.display()
+        ));
+    }
+
+    output
+}
+
+fn write_xspf(playlist: &Playlist, tracks: &[(&Item, PathBuf)]) -> String {
+    let mut output = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    output.push_str(&format!("  <title>{}</title>\n", escape(&playlist.name)));
+    if !playlist.description.is_empty() {
+        output.push_str(&format!(
+            "  <annotation>{}</annotation>\n",
+            escape(&playlist.description)
+        ));
+    }
+    output.push_str("  <trackList>\n");
+
+    for (track, location) in tracks {
+        output.push_str("    <track>\n");
+        output.push_str(&format!(
+            "      <location>{}</location>\n",
+            escape(&utf8_percent_encode(&location.to_string_lossy(), URI_SEGMENT).to_string())
+        ));
+        output.push_str(&format!("      <title>{}</title>\n", escape(&track.name)));
+        output.push_str(&format!(
+            "      <creator>{}</creator>\n",
+            escape(&artist_names(track))
+        ));
+        if !track.album.name.is_empty() {
+            output.push_str(&format!(
+                "      <album>{}</album>\n",
+                escape(&track.album.name)
+            ));
+        }
+        if track.duration_ms > 0 {
+            output.push_str(&format!(
+                "      <duration>{}</duration>\n",
+                track.duration_ms
+            ));
+        }
+        output.push_str("    </track>\n");
+    }
+
+    output.push_str("  </trackList>\n</playlist>\n");
+    output
+}
+
+fn artist_names(track: &Item) -> String {
+    if track.artists.is_empty() {
+        return track.artist.clone();
+    }
+
+    track
+        .artists
+        .iter()
+        .map(|artist| artist.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_resolve_against_the_playlist_folder() {
+        let m3u = parse_m3u(
+            "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:320,Daft Punk - One More Time\n\
+             01 One More Time.mp3\n..\\Other\\02.flac\n/abs/03.mp3\nhttp://radio/stream\n",
+        );
+        let base = Path::new("/music/Discovery");
+        let resolved: Vec<Option<PathBuf>> = m3u
+            .entries
+            .iter()
+            .map(|entry| resolve_entry(&m3u, base, entry))
+            .collect();
+
+        assert_eq!(m3u.title.as_deref(), Some("Mix"));
+        assert_eq!(
+            resolved,
+            vec![
+                Some(PathBuf::from("/music/Discovery/01 One More Time.mp3")),
+                Some(PathBuf::from("/music/Other/02.flac")),
+                Some(PathBuf::from("/abs/03.mp3")),
+                None,
+            ]
+        );
+
+        let xspf = parse_xspf(
+            "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\"><title>A &amp; B</title>\
+             <trackList><track><title>Ignored</title><location>01%20One.mp3</location></track>\
+             <track><location>file:///music/R%C3%A9mi.flac</location></track></trackList></playlist>",
+        )
+        .unwrap();
+        assert_eq!(xspf.title.as_deref(), Some("A & B"));
+        assert_eq!(
+            resolve_entry(&xspf, base, &xspf.entries[0]),
+            Some(PathBuf::from("/music/Discovery/01 One.mp3"))
+        );
+        assert_eq!(
+            resolve_entry(&xspf, base, &xspf.entries[1]),
+            Some(PathBuf::from("/music/Rémi.flac"))
+        );
+    }
+
+    #[test]
+    fn exports_use_relative_paths_and_durations() {
+        let track = |path: &str, name: &str, duration_ms| Item {
+            path: path.to_string(),
+            name: name.to_string(),
+            artist: "Daft Punk".to_string(),
+            duration_ms,
+            ..Default::default()
+        };
+        let playlist = Playlist {
+            id: "p".to_string(),
+            name: "Mix".to_string(),
+            description: String::new(),
+            cover: None,
+            source: None,
+            created_at: 0,
+            updated_at: 0,
+            rules: None,
+            track_ids: Vec::new(),
+            tracks: vec![
+                track("/music/Discovery/01 One.mp3", "One More Time", 320_357),
+                track("/music/Other/02.flac", "Aerodynamic", 0),
+            ],
+        };
+
+        // Fichier écrit sur disque : les titres hors de son dossier restent joignables
+        let base = Path::new("/music/Discovery");
+        assert_eq!(
+            write_playlist(&playlist, PlaylistFormat::M3u8, |track| Some(
+                relative_path(base, Path::new(&track.path))
+            )),
+            "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:320,Daft Punk - One More Time\n01 One.mp3\n\
+             #EXTINF:-1,Daft Punk - Aerodynamic\n../Other/02.flac\n"
+        );
+
+        // Liste servie par l'API : seuls les titres sous le dossier sont écrits
+        let xspf = write_playlist(&playlist, PlaylistFormat::Xspf, |track| {
+            path_under(base, track)
+        });
+        assert!(xspf.contains("<location>01%20One.mp3</location>"));
+        assert!(xspf.contains("<duration>320357</duration>"));
+        assert!(!xspf.contains("02.flac"));
+    }
+}
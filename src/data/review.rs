@@ -0,0 +1,183 @@
+use reqwest::Method;
+use rusqlite::Connection;
+use std::path::Path;
+
+use super::models::{Item, MatchDecision, MatchMethod, Page, ReviewItem};
+use super::tags::{keyed_by_file, local_track, read_tags};
+use crate::api::spotify::SpotifyClient;
+use crate::database::files::link_track;
+use crate::database::library::{load_track, prune_orphans, save_track};
+use crate::database::matches::{load_candidates, review_queue, save_candidates, save_decision};
+
+// Page de la file de revue des dossiers donnés, avec les candidats proposés pour chaque titre
+pub fn review_items(
+    conn: &Connection,
+    prefixes: &[String],
+    page: &Page,
+) -> rusqlite::Result<(Vec<ReviewItem>, usize)> {
+    let (ids, total) = review_queue(conn, prefixes, page)?;
+    let mut items = Vec::new();
+    for id in ids {
+        if let Some(track) = load_track(conn, &id)? {
+            let candidates = load_candidates(conn, &track.path)?;
+            items.push(ReviewItem { track, candidates });
+        }
+    }
+
+    Ok((items, total))
+}
+
+// Titre Spotify choisi par son identifiant, pour l'API comme pour la ligne de commande
+pub async fn fetch_spotify_track(spotify: &SpotifyClient, id: &str) -> Result<Item, String> {
+    let body = spotify
+        .send(Method::GET, &format!("tracks/{}", id))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    serde_json::from_str(&body).map_err(|err| err.to_string())
+}
+
+// Titre Spotify choisi à la main pour un fichier : confiance totale
+pub fn decided_track(spotify_track: &Item, artist: &str, path: &str) -> Item {
+    let mut track = spotify_track.clone();
+    track.artist = artist.to_string();
+    track.path = path.to_string();
+    track.match_confidence = Some(1.0);
+    track.match_method = Some(MatchMethod::Manual);
+    keyed_by_file(track)
+}
+
+// Enregistre la décision et remplace le titre indexé pour ce fichier
+pub fn apply_decision(
+    conn: &mut Connection,
+    current: &Item,
+    decision: MatchDecision,
+) -> Result<Item, String> {
+    let track = match &decision {
+        MatchDecision::Spotify(spotify_track) => {
+            decided_track(spotify_track, &current.artist, &current.path)
+        }
+        MatchDecision::Local if current.is_local => current.clone(),
+        // Titre associé automatiquement par erreur : on repart des tags du fichier
+        MatchDecision::Local => {
+            let tags = read_tags(Path::new(&current.path)).map_err(|failure| failure.message)?;
+            local_track(&tags, &current.path)
+        }
+    };
+
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    save_decision(&tx, &current.path, &decision)
+        .and_then(|_| save_track(&tx, &track))
+        .and_then(|_| link_track(&tx, &current.path, Some(&track.id)))
+        .and_then(|_| save_candidates(&tx, &current.path, &[]))
+        .and_then(|_| prune_orphans(&tx))
+        .and_then(|_| tx.commit())
+        .map_err(|err| err.to_string())?;
+
+    Ok(track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockSpotify;
+    use crate::data::jobs::ScanHandle;
+    use crate::data::libraries::Library;
+    use crate::data::models::TrackFilter;
+    use crate::data::utils::get_tracks_data;
+    use crate::database::database::Database;
+    use crate::database::library::{library_stats, load_tracks};
+    use audiotags::{AudioTagEdit, AudioTagWrite, Id3v2Tag};
+    use std::fs;
+
+    #[actix_web::test]
+    async fn decisions_are_kept_by_later_scans() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+
+        let path = dir.path().join("01.mp3");
+        fs::write(&path, b"").unwrap();
+        let mut tag = Id3v2Tag::new();
+        tag.set_artist("Daft Punk");
+        tag.set_title("Unreleased Jam");
+        tag.write_to_path(path.to_str().unwrap()).unwrap();
+
+        get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        let prefixes = [library.prefix()];
+        let (queued, total) = review_queue(&db.connection(), &prefixes, &Page::default()).unwrap();
+        assert_eq!(total, 1);
+        let elsewhere = ["/elsewhere/".to_string()];
+        assert_eq!(
+            review_queue(&db.connection(), &elsewhere, &Page::default()).unwrap(),
+            (Vec::new(), 0)
+        );
+        assert_eq!(queued.len(), 1);
+        let stats = library_stats(&db.connection(), &library.prefix()).unwrap();
+        assert_eq!(
+            (stats.tracks, stats.local_tracks, stats.to_review),
+            (1, 1, 1)
+        );
+        assert_eq!(
+            library_stats(&db.connection(), "/elsewhere/")
+                .unwrap()
+                .tracks,
+            0
+        );
+        let current = load_track(&db.connection(), &queued[0]).unwrap().unwrap();
+        let candidates = load_candidates(&db.connection(), &current.path).unwrap();
+        let chosen = candidates
+            .iter()
+            .find(|candidate| candidate.track.name == "One More Time")
+            .unwrap();
+
+        let decision = MatchDecision::Spotify(Box::new(chosen.track.clone()));
+        let track = apply_decision(&mut db.connection(), &current, decision).unwrap();
+        assert_eq!(track.id, current.id);
+        assert_eq!(track.spotify_id.as_deref(), Some("0DiWol3AO6WpXZgp0goxAV"));
+        assert!(review_queue(&db.connection(), &prefixes, &Page::default())
+            .unwrap()
+            .0
+            .is_empty());
+
+        // La réanalyse complète ne relance pas de recherche pour ce fichier
+        let searches = mock.searches().len();
+        let report = get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(report.needs_review, 0);
+        assert_eq!(mock.searches().len(), searches);
+
+        let filter = TrackFilter {
+            path: dir_str,
+            ..Default::default()
+        };
+        let tracks = load_tracks(&db.connection(), &filter, &Page::default())
+            .unwrap()
+            .0;
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].id, current.id);
+        assert_eq!(
+            tracks[0].spotify_id.as_deref(),
+            Some("0DiWol3AO6WpXZgp0goxAV")
+        );
+        assert_eq!(tracks[0].match_confidence, Some(1.0));
+
+        // Retour au titre local, qui ne revient pas dans la file de revue
+        let current = tracks[0].clone();
+        let track = apply_decision(&mut db.connection(), &current, MatchDecision::Local).unwrap();
+        assert!(track.is_local);
+        assert_eq!(track.name, "Unreleased Jam");
+        assert!(review_queue(&db.connection(), &prefixes, &Page::default())
+            .unwrap()
+            .0
+            .is_empty());
+        mock.stop().await;
+    }
+}
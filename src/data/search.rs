@@ -0,0 +1,154 @@
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// Filtres utilisables dans une recherche : `artist:daft album:"random access" year:2013`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchField {
+    Artist,
+    Album,
+    Year,
+}
+
+impl SearchField {
+    pub fn from_name(name: &str) -> Option<SearchField> {
+        match name.to_lowercase().as_str() {
+            "artist" => Some(SearchField::Artist),
+            "album" => Some(SearchField::Album),
+            "year" => Some(SearchField::Year),
+            _ => None,
+        }
+    }
+
+    pub fn column(&self) -> &'static str {
+        match self {
+            SearchField::Artist => "artist",
+            SearchField::Album => "album",
+            SearchField::Year => "year",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchKind {
+    Tracks,
+    Albums,
+    Artists,
+}
+
+pub const SEARCH_KINDS: [SearchKind; 3] =
+    [SearchKind::Tracks, SearchKind::Albums, SearchKind::Artists];
+
+impl SearchKind {
+    pub fn from_name(name: &str) -> Option<SearchKind> {
+        SEARCH_KINDS.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchKind::Tracks => "tracks",
+            SearchKind::Albums => "albums",
+            SearchKind::Artists => "artists",
+        }
+    }
+
+    // Valeur de search_items.kind
+    pub fn item_kind(&self) -> &'static str {
+        match self {
+            SearchKind::Tracks => "track",
+            SearchKind::Albums => "album",
+            SearchKind::Artists => "artist",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchToken {
+    pub field: Option<SearchField>,
+    pub terms: Vec<String>,
+    // Entre guillemets : termes exacts et consécutifs
+    pub phrase: bool,
+}
+
+pub fn parse_query(query: &str) -> Vec<SearchToken> {
+    split_query(query)
+        .into_iter()
+        .filter_map(|piece| {
+            let (field, value) = match piece.split_once(':') {
+                Some((name, value)) if !value.is_empty() => match SearchField::from_name(name) {
+                    Some(field) => (Some(field), value),
+                    None => (None, piece.as_str()),
+                },
+                _ => (None, piece.as_str()),
+            };
+
+            let terms = search_terms(value);
+            (!terms.is_empty()).then(|| SearchToken {
+                field,
+                terms,
+                phrase: value.contains('"'),
+            })
+        })
+        .collect()
+}
+
+// Découpe sur les espaces, sauf entre guillemets
+fn split_query(query: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            current.push(c);
+        } else if c.is_whitespace() && !quoted {
+            if !current.is_empty() {
+                pieces.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+// Même découpage que le tokenizer unicode61 de l'index : minuscules, sans accents
+pub fn search_terms(value: &str) -> Vec<String> {
+    value
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_split_into_filters_and_terms() {
+        let token = |field, terms: &[&str], phrase| SearchToken {
+            field,
+            terms: terms.iter().map(|term| term.to_string()).collect(),
+            phrase,
+        };
+
+        assert_eq!(
+            parse_query("  Beyoncé artist:\"Daft Punk\" year:2013 foo:bar album: -  "),
+            vec![
+                token(None, &["beyonce"], false),
+                token(Some(SearchField::Artist), &["daft", "punk"], true),
+                token(Some(SearchField::Year), &["2013"], false),
+                token(None, &["foo", "bar"], false),
+                token(None, &["album"], false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use audiotags::Tag;
+use id3::TagLike;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::models::{Album, Artist, ExternalIds, FailureReason, FileFailure, Item};
+
+// Métadonnées lues dans le fichier, utilisées pour la recherche Spotify et les titres locaux
+#[derive(Debug, Default, Clone)]
+pub struct TrackTags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: i64,
+    pub disc_number: i64,
+    pub year: Option<i32>,
+    pub duration_ms: i64,
+    pub isrc: Option<String>,
+    pub cover: Option<Vec<u8>>,
+    // Format sans lecteur de tags : titre local, sans recherche Spotify
+    pub untagged: bool,
+}
+
+pub fn read_tags(file_path: &Path) -> Result<TrackTags, FileFailure> {
+    let path = file_path.to_string_lossy().into_owned();
+    let tag = match Tag::new().read_from_path(file_path) {
+        Ok(tag) => tag,
+        Err(
+            audiotags::Error::UnknownFileExtension(_)
+            | audiotags::Error::UnsupportedFormat(_)
+            | audiotags::Error::UnsupportedMimeType(_),
+        ) => return Ok(file_name_tags(file_path)),
+        Err(err) => return Err(tag_failure(&path, err)),
+    };
+
+    let artist = normalize(tag.artist().unwrap_or(""));
+    let mut title = normalize(tag.title().unwrap_or(""));
+    if title.is_empty() {
+        title = file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+
+    // ID3 stocke la durée (TLEN) en millisecondes, FLAC et MP4 la donnent en secondes
+    let is_id3 = file_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"));
+    let duration_ms = match tag.duration() {
+        Some(duration) if is_id3 => duration as i64,
+        Some(duration) => (duration * 1000.0) as i64,
+        None => 0,
+    };
+
+    Ok(TrackTags {
+        album: normalize(tag.album_title().unwrap_or("")),
+        album_artist: normalize(tag.album_artist().unwrap_or(&artist)),
+        track_number: tag.track_number().map(i64::from).unwrap_or(0),
+        disc_number: tag.disc_number().map(i64::from).unwrap_or(1),
+        year: tag.year(),
+        duration_ms,
+        isrc: read_isrc(file_path),
+        cover: tag.album_cover().map(|picture| picture.data.to_vec()),
+        title,
+        artist,
+        untagged: false,
+    })
+}
+
+// Ogg, WMA, AAC ou WAV : titre tiré du nom du fichier, album du nom de son dossier
+fn file_name_tags(file_path: &Path) -> TrackTags {
+    let name = |name: Option<&std::ffi::OsStr>| {
+        name.map(|name| normalize(&name.to_string_lossy()))
+            .unwrap_or_default()
+    };
+
+    TrackTags {
+        title: name(file_path.file_stem()),
+        album: name(file_path.parent().and_then(Path::file_name)),
+        disc_number: 1,
+        untagged: true,
+        ..Default::default()
+    }
+}
+
+// audiotags n'expose pas l'ISRC : lecture directe du tag selon le format
+fn read_isrc(file_path: &Path) -> Option<String> {
+    let extension = file_path.extension()?.to_string_lossy().to_lowercase();
+
+    let isrc = match extension.as_str() {
+        "mp3" => id3::Tag::read_from_path(file_path)
+            .ok()?
+            .get("TSRC")?
+            .content()
+            .text()?
+            .to_string(),
+        "flac" => metaflac::Tag::read_from_path(file_path)
+            .ok()?
+            .get_vorbis("ISRC")?
+            .next()?
+            .to_string(),
+        "m4a" | "mp4" => mp4ameta::Tag::read_from_path(file_path)
+            .ok()?
+            .isrc()?
+            .to_string(),
+        _ => return None,
+    };
+
+    // Format ISRC : CC-XXX-YY-NNNNN, parfois écrit avec des tirets
+    let isrc: String = isrc
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_uppercase();
+    (isrc.len() == 12).then_some(isrc)
+}
+
+// Titre absent de Spotify, construit uniquement à partir des tags du fichier
+pub fn local_track(tags: &TrackTags, path: &str) -> Item {
+    let artist = local_artist(&tags.artist);
+    let album_artist = local_artist(&tags.album_artist);
+
+    let album = Album {
+        artist: tags.album_artist.clone(),
+        album_type: "album".to_string(),
+        id: local_id(&["album", &album_artist.id, &tags.album.to_lowercase()]),
+        name: tags.album.clone(),
+        release_date: tags.year.map(|year| year.to_string()).unwrap_or_default(),
+        release_date_precision: "year".to_string(),
+        type_field: "album".to_string(),
+        artists: vec![album_artist],
+        ..Default::default()
+    };
+
+    // Même format que les URI des fichiers locaux dans Spotify
+    let uri = format!(
+        "spotify:local:{}:{}:{}:{}",
+        encode(&tags.artist),
+        encode(&tags.album),
+        encode(&tags.title),
+        tags.duration_ms / 1000
+    );
+
+    Item {
+        artist: tags.artist.clone(),
+        album,
+        artists: vec![artist],
+        disc_number: tags.disc_number,
+        duration_ms: tags.duration_ms,
+        external_ids: ExternalIds {
+            isrc: tags.isrc.clone().unwrap_or_default(),
+        },
+        id: track_key(path),
+        name: tags.title.clone(),
+        track_number: tags.track_number,
+        type_field: "track".to_string(),
+        uri,
+        is_local: true,
+        path: path.to_string(),
+        ..Default::default()
+    }
+}
+
+// Titre Spotify enregistré sous l'identifiant de son fichier, l'id Spotify gardé à part
+pub fn keyed_by_file(mut track: Item) -> Item {
+    if !track.is_local {
+        let key = track_key(&track.path);
+        track.spotify_id = Some(std::mem::replace(&mut track.id, key));
+    }
+    track
+}
+
+// Identifiant d'un titre dans l'index : un par fichier, même s'ils ont la même correspondance
+pub fn track_key(path: &str) -> String {
+    local_id(&["track", path])
+}
+
+fn local_artist(name: &str) -> Artist {
+    Artist {
+        id: local_id(&["artist", &name.to_lowercase()]),
+        name: name.to_string(),
+        type_field: "artist".to_string(),
+        ..Default::default()
+    }
+}
+
+// Identifiant stable, dérivé des mêmes informations à chaque analyse
+fn local_id(parts: &[&str]) -> String {
+    let hash: String = Sha256::digest(parts.join("\u{0}").as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("local-{}", &hash[..22])
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, NON_ALPHANUMERIC).to_string()
+}
+
+fn tag_failure(path: &str, err: audiotags::Error) -> FileFailure {
+    let reason = match err {
+        audiotags::Error::ReadError { .. } | audiotags::Error::IOError(_) => {
+            FailureReason::ReadError
+        }
+        _ => FailureReason::NoTags,
+    };
+
+    FileFailure {
+        path: path.to_string(),
+        reason,
+        message: err.to_string(),
+    }
+}
+
+fn normalize(title: &str) -> String {
+    title.replace('\\', "")
+}
@@ -0,0 +1,446 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::{mpsc, watch, Semaphore};
+use uuid::Uuid;
+
+use super::models::Item;
+
+const DEFAULT_ENCODER: &str = "ffmpeg";
+const DEFAULT_CACHE_DIR: &str = "cache/transcodes";
+const DEFAULT_MAX_JOBS: usize = 2;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Profile {
+    Opus128,
+    Mp3320,
+    Aac256,
+}
+
+pub const PROFILES: [Profile; 3] = [Profile::Opus128, Profile::Mp3320, Profile::Aac256];
+
+impl Profile {
+    pub fn from_name(name: &str) -> Option<Profile> {
+        PROFILES.into_iter().find(|profile| profile.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Opus128 => "opus-128",
+            Profile::Mp3320 => "mp3-320",
+            Profile::Aac256 => "aac-256",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Profile::Opus128 => "audio/ogg",
+            Profile::Mp3320 => "audio/mpeg",
+            Profile::Aac256 => "audio/mp4",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Profile::Opus128 => "opus",
+            Profile::Mp3320 => "mp3",
+            Profile::Aac256 => "m4a",
+        }
+    }
+
+    // Codec, débit et conteneur passés à l'encodeur ; le MP4 est fragmenté pour être écrit d'un trait
+    fn encoder_args(&self) -> &'static [&'static str] {
+        match self {
+            Profile::Opus128 => &["-c:a", "libopus", "-b:a", "128k", "-f", "ogg"],
+            Profile::Mp3320 => &["-c:a", "libmp3lame", "-b:a", "320k", "-f", "mp3"],
+            Profile::Aac256 => &[
+                "-c:a",
+                "aac",
+                "-b:a",
+                "256k",
+                "-movflags",
+                "frag_keyframe+empty_moov",
+                "-f",
+                "ipod",
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscodeConfig {
+    pub encoder: String,
+    pub cache_dir: PathBuf,
+    pub max_jobs: usize,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> TranscodeConfig {
+        TranscodeConfig {
+            encoder: DEFAULT_ENCODER.to_string(),
+            cache_dir: DEFAULT_CACHE_DIR.into(),
+            max_jobs: DEFAULT_MAX_JOBS,
+        }
+    }
+}
+
+// Transcodages en cours, partagés par les lectures d'un même titre et profil,
+// et nombre d'encodeurs lancés en même temps limité par `max_jobs`
+#[derive(Clone)]
+pub struct Transcoder {
+    config: TranscodeConfig,
+    jobs: Arc<Mutex<HashMap<(String, Profile), Job>>>,
+    slots: Arc<Semaphore>,
+}
+
+// Fichier partiel et avancement d'un transcodage en cours
+#[derive(Clone)]
+struct Job {
+    partial: PathBuf,
+    state: watch::Receiver<JobState>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct JobState {
+    written: u64,
+    finished: Option<Result<(), String>>,
+}
+
+pub enum Transcoded {
+    // Fichier complet en cache, servi avec Range et ETag
+    Cached(PathBuf),
+    // Sortie de l'encodeur envoyée au fur et à mesure qu'elle remplit le cache
+    Streaming(TranscodeStream),
+}
+
+impl Transcoder {
+    pub fn new(config: TranscodeConfig) -> Transcoder {
+        let slots = Arc::new(Semaphore::new(config.max_jobs.max(1)));
+
+        Transcoder {
+            config,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            slots,
+        }
+    }
+
+    // Version transcodée du titre, réutilisée tant que la source n'a pas changé ;
+    // `source` est le fichier du titre déjà vérifié par l'appelant
+    pub async fn transcode(
+        &self,
+        track: &Item,
+        source: &Path,
+        profile: Profile,
+    ) -> Result<Transcoded, String> {
+        let cached = self.cached_path(track, profile);
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let key = (track.id.clone(), profile);
+            match jobs.get(&key) {
+                Some(job) => job.clone(),
+                None if is_fresh(&cached, source) => return Ok(Transcoded::Cached(cached)),
+                None => {
+                    let job = self.start(track, source, profile, cached)?;
+                    jobs.insert(key, job.clone());
+                    job
+                }
+            }
+        };
+
+        // Le fichier partiel est ouvert avant son renommage : la lecture continue ensuite
+        let file = tokio::fs::File::open(&job.partial)
+            .await
+            .map_err(|err| err.to_string())?;
+        let (sender, mut receiver) = mpsc::channel(4);
+        tokio::spawn(follow(file, job.state, sender));
+
+        // Un encodeur qui échoue d'emblée donne une erreur plutôt qu'une réponse vide
+        match receiver.recv().await {
+            Some(Ok(first)) => Ok(Transcoded::Streaming(TranscodeStream {
+                first: Some(first),
+                receiver,
+            })),
+            Some(Err(err)) => Err(err.to_string()),
+            None => Ok(Transcoded::Cached(self.cached_path(track, profile))),
+        }
+    }
+
+    fn cached_path(&self, track: &Item, profile: Profile) -> PathBuf {
+        self.config.cache_dir.join(format!(
+            "{}-{}.{}",
+            track.id,
+            profile.name(),
+            profile.extension()
+        ))
+    }
+
+    // Crée le fichier partiel puis lance l'encodeur dès qu'une place se libère
+    fn start(
+        &self,
+        track: &Item,
+        source: &Path,
+        profile: Profile,
+        cached: PathBuf,
+    ) -> Result<Job, String> {
+        fs::create_dir_all(&self.config.cache_dir).map_err(|err| err.to_string())?;
+
+        // Fichier temporaire renommé à la fin : un transcodage interrompu n'est jamais servi
+        let partial = self.config.cache_dir.join(format!(
+            "{}-{}.{}.part",
+            track.id,
+            Uuid::new_v4(),
+            profile.extension()
+        ));
+        let output = fs::File::create(&partial).map_err(|err| err.to_string())?;
+        let (sender, state) = watch::channel(JobState::default());
+
+        let transcoder = self.clone();
+        let key = (track.id.clone(), profile);
+        let (source, track_path) = (source.to_path_buf(), track.path.clone());
+        let job = Job { partial, state };
+        let partial = job.partial.clone();
+        tokio::spawn(async move {
+            let result = transcoder
+                .encode(&source, profile, tokio::fs::File::from_std(output), &sender)
+                .await
+                .map_err(|err| format!("Transcoding {} failed: {}", track_path, err));
+
+            // Retiré des transcodages en cours avant le renommage : une nouvelle lecture
+            // trouve soit le transcodage, soit le fichier en cache
+            let result = {
+                let mut jobs = transcoder.jobs.lock().unwrap();
+                jobs.remove(&key);
+                result.and_then(|_| fs::rename(&partial, &cached).map_err(|err| err.to_string()))
+            };
+            if let Err(err) = &result {
+                eprintln!("{}", err);
+                let _ = fs::remove_file(&partial);
+            }
+            sender.send_modify(|state| state.finished = Some(result));
+        });
+
+        Ok(job)
+    }
+
+    async fn encode(
+        &self,
+        source: &Path,
+        profile: Profile,
+        mut output: tokio::fs::File,
+        state: &watch::Sender<JobState>,
+    ) -> Result<(), String> {
+        let _slot = self.slots.acquire().await.map_err(|err| err.to_string())?;
+
+        let mut child = Command::new(&self.config.encoder)
+            .args(["-nostdin", "-v", "error", "-y", "-i"])
+            .arg(source)
+            .args(["-vn", "-map_metadata", "0"])
+            .args(profile.encoder_args())
+            .arg("pipe:1")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| format!("Unable to run {}: {}", self.config.encoder, err))?;
+
+        let mut stdout = child.stdout.take().ok_or("No encoder output")?;
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut written = 0;
+        loop {
+            let read = stdout
+                .read(&mut buffer)
+                .await
+                .map_err(|err| err.to_string())?;
+            if read == 0 {
+                break;
+            }
+            // Écrit et vidé avant d'être annoncé aux lectures en cours
+            output
+                .write_all(&buffer[..read])
+                .await
+                .and(output.flush().await)
+                .map_err(|err| err.to_string())?;
+            written += read as u64;
+            state.send_modify(|state| state.written = written);
+        }
+
+        let result = child
+            .wait_with_output()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !result.status.success() {
+            return Err(String::from_utf8_lossy(&result.stderr).trim().to_string());
+        }
+        output.sync_all().await.map_err(|err| err.to_string())
+    }
+}
+
+// Copie le fichier partiel vers la réponse à mesure que l'encodeur l'écrit
+async fn follow(
+    mut file: tokio::fs::File,
+    mut state: watch::Receiver<JobState>,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut sent = 0;
+
+    loop {
+        let current = state.borrow_and_update().clone();
+        while sent < current.written {
+            let wanted = CHUNK_SIZE.min((current.written - sent) as usize);
+            let read = match file.read(&mut buffer[..wanted]).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) => {
+                    let _ = sender.send(Err(err)).await;
+                    return;
+                }
+            };
+            sent += read as u64;
+            // Client parti : le transcodage continue pour le cache
+            if sender
+                .send(Ok(Bytes::copy_from_slice(&buffer[..read])))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        match current.finished {
+            Some(Ok(())) if sent >= current.written => return,
+            Some(Err(err)) => {
+                let _ = sender.send(Err(io::Error::other(err))).await;
+                return;
+            }
+            _ => {}
+        }
+        if state.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+// Corps de réponse alimenté par `follow`, sans longueur connue à l'avance
+pub struct TranscodeStream {
+    first: Option<Bytes>,
+    receiver: mpsc::Receiver<io::Result<Bytes>>,
+}
+
+impl MessageBody for TranscodeStream {
+    type Error = io::Error;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, io::Error>>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(Ok(first)));
+        }
+        self.receiver.poll_recv(cx)
+    }
+}
+
+fn is_fresh(cached: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (modified(cached), modified(source)) {
+        (Ok(cached), Ok(source)) => cached >= source,
+        _ => false,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[actix_web::test]
+    async fn transcodes_stream_once_then_come_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("01.flac");
+        fs::write(&source, b"flac").unwrap();
+
+        // Faux encodeur : écrit la source sur sa sortie, un peu lentement, et compte ses appels
+        let calls = dir.path().join("calls");
+        let encoder = dir.path().join("encoder.sh");
+        fs::write(
+            &encoder,
+            format!(
+                "#!/bin/sh\necho run >> {}\nwhile [ \"$1\" != \"-i\" ]; do shift; done\n\
+                 sleep 0.2\ncat \"$2\"\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&encoder, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let transcoder = Transcoder::new(TranscodeConfig {
+            encoder: encoder.to_string_lossy().into_owned(),
+            cache_dir: dir.path().join("cache"),
+            max_jobs: 1,
+        });
+        let track = Item {
+            id: "local-track".to_string(),
+            path: source.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let calls_count = || fs::read_to_string(&calls).unwrap().lines().count();
+        let streamed = |transcoded: Result<Transcoded, String>| async move {
+            match transcoded.unwrap() {
+                Transcoded::Streaming(body) => actix_web::body::to_bytes(body).await.unwrap(),
+                Transcoded::Cached(path) => panic!("{} already cached", path.display()),
+            }
+        };
+
+        // Deux lectures simultanées partagent le même encodeur
+        let (first, second) = tokio::join!(
+            transcoder.transcode(&track, &source, Profile::Opus128),
+            transcoder.transcode(&track, &source, Profile::Opus128),
+        );
+        assert_eq!(streamed(first).await, "flac");
+        assert_eq!(streamed(second).await, "flac");
+        assert_eq!(calls_count(), 1);
+
+        let Ok(Transcoded::Cached(cached)) = transcoder
+            .transcode(&track, &source, Profile::Opus128)
+            .await
+        else {
+            panic!("transcoded file not cached");
+        };
+        assert_eq!(cached.file_name().unwrap(), "local-track-opus-128.opus");
+        assert_eq!(fs::read(&cached).unwrap(), b"flac");
+
+        let mp3 = transcoder.transcode(&track, &source, Profile::Mp3320).await;
+        assert_eq!(streamed(mp3).await, "flac");
+        assert_eq!(calls_count(), 2);
+
+        // Un encodeur introuvable est une erreur, sans fichier partiel laissé dans le cache
+        let missing = Transcoder::new(TranscodeConfig {
+            encoder: dir.path().join("missing").to_string_lossy().into_owned(),
+            cache_dir: dir.path().join("cache"),
+            max_jobs: 1,
+        });
+        assert!(missing
+            .transcode(&track, &source, Profile::Aac256)
+            .await
+            .is_err());
+        assert_eq!(fs::read_dir(dir.path().join("cache")).unwrap().count(), 2);
+    }
+}
@@ -1,167 +1,1274 @@
-use audiotags::Tag;
-// use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use actix_web::web;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use reqwest::Method;
-use std::collections::VecDeque;
-use std::fs;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Read};
 use std::path::Path;
 use std::path::PathBuf;
-use tokio::time::Duration;
+use std::time::UNIX_EPOCH;
 
-// use super::models::Album;
-use super::models::Data;
+use super::covers::{cover_from, find_cover, Cover};
+use super::jobs::ScanHandle;
+use super::libraries::Library;
+use super::matcher::{score_candidates, MATCH_THRESHOLD};
+use super::models::FailureReason;
+use super::models::FileFailure;
+use super::models::FileFingerprint;
 use super::models::Item;
+use super::models::MatchCandidate;
+use super::models::MatchDecision;
+use super::models::MatchMethod;
+use super::models::PlaylistImport;
+use super::models::ScanReport;
 use super::models::SpotifySearchResponse;
+use super::playlist_files::{is_playlist_file, read_playlist_file, resolve_entry};
+use super::review::decided_track;
+use super::tags::{keyed_by_file, local_track, read_tags, TrackTags};
 
-use crate::api::spotify::send_spotify_request;
+use crate::api::spotify::SpotifyClient;
+use crate::database::covers::{missing_album_images, save_album_cover, save_album_image};
+use crate::database::database::Database;
+use crate::database::files::{
+    indexed_paths, link_track, load_fingerprint, mark_retry, needs_retry, remove_file,
+    save_fingerprint,
+};
+use crate::database::library::{prune_orphans, remove_track, save_track, track_id_for_path};
+use crate::database::matches::{load_decision, save_candidates};
+use crate::database::playlists::{delete_playlist, imported_playlists, save_imported_playlist};
 
-pub async fn get_tracks_data(dir: &Path) -> Option<Result<Data, String>> {
-    let mut data = Data {
-        tracks: Vec::new(),
-        albums: Vec::new(),
-        artists: Vec::new(),
+const SEARCH_LIMIT: usize = 10;
+const REVIEW_CANDIDATES: usize = 5;
+
+#[derive(Debug, PartialEq)]
+enum FileStatus {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+pub async fn get_tracks_data(
+    db: &Database,
+    spotify: &SpotifyClient,
+    library: &Library,
+    changed_only: bool,
+    handle: &ScanHandle,
+) -> Result<ScanReport, String> {
+    let mut report = ScanReport::default();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    // Liste complète d'abord, pour connaître le total et estimer la durée restante
+    let (files, playlist_files, unreadable_dirs) = blocking({
+        let library = library.clone();
+        move || list_files(&library, &library.path)
+    })
+    .await?;
+    handle.update(|progress| progress.total = files.len());
+
+    report.failures.extend(unreadable_dirs.iter().cloned());
+
+    for path in files {
+        if handle.is_cancelled() {
+            return Err("Scan cancelled".to_string());
+        }
+
+        seen.insert(path.to_string_lossy().into_owned());
+
+        let outcome = index_file(db, spotify, &path, changed_only).await?;
+        handle.update(|progress| {
+            progress.seen += 1;
+            match &outcome {
+                FileOutcome::Unchanged => {}
+                FileOutcome::Indexed { track, .. } if track.is_local => progress.unmatched += 1,
+                FileOutcome::Indexed { .. } => progress.matched += 1,
+                FileOutcome::Failed(file_failure) => match file_failure.reason {
+                    FailureReason::ReadError | FailureReason::RemoteError => progress.errors += 1,
+                    _ => progress.unmatched += 1,
+                },
+            }
+        });
+        record_outcome(&mut report, outcome);
+    }
+
+    cache_artwork(db, spotify, handle).await?;
+
+    let (db, library) = (db.clone(), library.clone());
+    blocking(move || {
+        import_playlists(&db, &playlist_files, &mut report)?;
+        remove_missing(
+            &db,
+            &library,
+            &seen,
+            &playlist_files,
+            &unreadable_dirs,
+            &mut report,
+        )?;
+        relative_report(&library, &mut report);
+        Ok(report)
+    })
+    .await
+}
+
+// Fichiers indexés qui n'existent plus sur le disque, hors dossiers illisibles
+fn remove_missing(
+    db: &Database,
+    library: &Library,
+    seen: &HashSet<String>,
+    playlist_files: &[PathBuf],
+    unreadable_dirs: &[FileFailure],
+    report: &mut ScanReport,
+) -> Result<(), String> {
+    let dir_str = library.prefix();
+    let mut conn = db.connection();
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    let is_unreadable = |path: &str| {
+        unreadable_dirs
+            .iter()
+            .any(|failure| Path::new(path).starts_with(&failure.path))
+    };
+    for path in indexed_paths(&tx, &dir_str).map_err(|err| err.to_string())? {
+        if !seen.contains(&path) && !is_unreadable(&path) {
+            remove_file(&tx, &path).map_err(|err| err.to_string())?;
+            report.removed += 1;
+        }
+    }
+    for (id, source) in imported_playlists(&tx, &dir_str).map_err(|err| err.to_string())? {
+        if !playlist_files
+            .iter()
+            .any(|file| file.to_string_lossy() == source)
+            && !is_unreadable(&source)
+        {
+            delete_playlist(&tx, &id).map_err(|err| err.to_string())?;
+        }
+    }
+    prune_orphans(&tx)
+        .and_then(|_| tx.commit())
+        .map_err(|err| err.to_string())
+}
+
+// Hachage, lecture des tags et écritures SQLite sur le pool bloquant : le worker HTTP reste libre
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    web::block(f).await.map_err(|err| err.to_string())?
+}
+
+// Mise à jour limitée aux chemins modifiés, sans parcourir le reste de la bibliothèque
+pub async fn update_paths(
+    db: &Database,
+    spotify: &SpotifyClient,
+    library: &Library,
+    paths: &[PathBuf],
+) -> Result<ScanReport, String> {
+    let (files, playlist_files, mut report) = blocking({
+        let (db, library, paths) = (db.clone(), library.clone(), paths.to_vec());
+        move || changed_files(&db, &library, &paths)
+    })
+    .await?;
+
+    for path in files {
+        let outcome = index_file(db, spotify, &path, true).await?;
+        record_outcome(&mut report, outcome);
+    }
+
+    cache_artwork(db, spotify, &ScanHandle::default()).await?;
+
+    let (db, library) = (db.clone(), library.clone());
+    blocking(move || {
+        import_playlists(&db, &playlist_files, &mut report)?;
+        prune_orphans(&db.connection()).map_err(|err| err.to_string())?;
+        relative_report(&library, &mut report);
+        Ok(report)
+    })
+    .await
+}
+
+// Fichiers à réindexer parmi les chemins modifiés ; ceux qui ont disparu sont retirés de l'index
+fn changed_files(
+    db: &Database,
+    library: &Library,
+    paths: &[PathBuf],
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>, ScanReport), String> {
+    let mut report = ScanReport::default();
+    let mut files = Vec::new();
+    let mut playlist_files = Vec::new();
+
+    for path in paths {
+        // Un chemin disparu n'a plus de cible à vérifier : il est seulement retiré de l'index
+        let path = &if path.exists() {
+            match library.confine(path) {
+                Ok(path) => path,
+                Err(err) => {
+                    report.failures.push(failure(
+                        &path.to_string_lossy(),
+                        FailureReason::ReadError,
+                        err.to_string(),
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            path.clone()
+        };
+
+        if path.is_dir() {
+            let (dir_files, dir_playlists, unreadable_dirs) = list_files(library, path)?;
+            files.extend(dir_files);
+            playlist_files.extend(dir_playlists);
+            report.failures.extend(unreadable_dirs);
+        } else if path.is_file() && library.contains(path) {
+            if path.extension().is_some_and(is_audio_file) {
+                files.push(path.clone());
+            } else if path.extension().is_some_and(is_playlist_file) {
+                playlist_files.push(path.clone());
+            }
+        } else {
+            // Fichier ou dossier disparu, ou désormais exclu : tout ce qui était indexé à cet endroit est retiré
+            let path_str = path.to_string_lossy().into_owned();
+            let mut conn = db.connection();
+            let tx = conn.transaction().map_err(|err| err.to_string())?;
+            for indexed in indexed_paths(&tx, &path_str).map_err(|err| err.to_string())? {
+                if Path::new(&indexed).starts_with(path) {
+                    remove_file(&tx, &indexed).map_err(|err| err.to_string())?;
+                    report.removed += 1;
+                }
+            }
+            for (id, source) in imported_playlists(&tx, &path_str).map_err(|err| err.to_string())? {
+                if Path::new(&source).starts_with(path) {
+                    delete_playlist(&tx, &id).map_err(|err| err.to_string())?;
+                }
+            }
+            tx.commit().map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok((files, playlist_files, report))
+}
+
+// Le rapport est renvoyé aux clients : chemins relatifs au dossier
+fn relative_report(library: &Library, report: &mut ScanReport) {
+    for file_failure in &mut report.failures {
+        file_failure.path = library.relative(&file_failure.path).unwrap_or_default();
+    }
+    for import in &mut report.playlists {
+        import.path = library.relative(&import.path).unwrap_or_default();
+    }
+}
+
+enum FileOutcome {
+    Unchanged,
+    Indexed {
+        track: Box<Item>,
+        status: FileStatus,
+        needs_review: bool,
+    },
+    Failed(FileFailure),
+}
+
+// Fichier lu hors du runtime : empreinte, décision de la revue, tags et pochette
+enum FileRead {
+    Done(FileOutcome),
+    Read(Box<ReadFile>),
+}
+
+struct ReadFile {
+    fingerprint: FileFingerprint,
+    status: FileStatus,
+    decision: Option<MatchDecision>,
+    tags: Result<(TrackTags, Option<Cover>), FileFailure>,
+}
+
+// Empreinte, tags puis Spotify pour un seul fichier audio, enregistrés dans l'index
+async fn index_file(
+    db: &Database,
+    spotify: &SpotifyClient,
+    path: &Path,
+    changed_only: bool,
+) -> Result<FileOutcome, String> {
+    let read = blocking({
+        let (db, path) = (db.clone(), path.to_path_buf());
+        move || read_file(&db, &path, changed_only)
+    })
+    .await?;
+    let ReadFile {
+        fingerprint,
+        status,
+        decision,
+        tags,
+    } = match read {
+        FileRead::Done(outcome) => return Ok(outcome),
+        FileRead::Read(file) => *file,
+    };
+
+    let path_str = path.to_string_lossy().into_owned();
+    let resolution = match tags {
+        Ok((tags, cover)) => {
+            // Spotify injoignable : le titre est indexé localement et retenté à la prochaine analyse
+            let fallback = local_track(&tags, &path_str);
+            match match_track(spotify, path_str, tags, decision.as_ref()).await {
+                Ok((track, candidates)) => Ok(Resolution {
+                    track,
+                    candidates,
+                    cover,
+                    remote_failure: None,
+                }),
+                Err(file_failure) if file_failure.reason == FailureReason::RemoteError => {
+                    Ok(Resolution {
+                        track: fallback,
+                        candidates: Vec::new(),
+                        cover,
+                        remote_failure: Some(file_failure),
+                    })
+                }
+                Err(file_failure) => Err(file_failure),
+            }
+        }
+        Err(file_failure) => Err(file_failure),
+    };
+
+    let db = db.clone();
+    blocking(move || store_file(&db, &fingerprint, status, resolution)).await
+}
+
+fn read_file(db: &Database, path: &Path, changed_only: bool) -> Result<FileRead, String> {
+    let path_str = path.to_string_lossy().into_owned();
+
+    let previous = load_fingerprint(&db.connection(), &path_str).map_err(|err| err.to_string())?;
+    let (fingerprint, status) = match file_fingerprint(path, previous.as_ref()) {
+        Ok(result) => result,
+        Err(err) => {
+            return Ok(FileRead::Done(FileOutcome::Failed(failure(
+                &path_str,
+                FailureReason::ReadError,
+                err.to_string(),
+            ))))
+        }
+    };
+
+    // Fichier identique à la dernière analyse : ni tags ni Spotify
+    if changed_only
+        && status == FileStatus::Unchanged
+        && !needs_retry(&db.connection(), &path_str).map_err(|err| err.to_string())?
+    {
+        if previous.as_ref() != Some(&fingerprint) {
+            save_fingerprint(&db.connection(), &fingerprint).map_err(|err| err.to_string())?;
+        }
+        return Ok(FileRead::Done(FileOutcome::Unchanged));
+    }
+
+    let decision = load_decision(&db.connection(), &path_str).map_err(|err| err.to_string())?;
+    let tags = read_tags(path).map(|mut tags| {
+        let cover = find_cover(path, tags.cover.take());
+        (tags, cover)
+    });
+
+    Ok(FileRead::Read(Box::new(ReadFile {
+        fingerprint,
+        status,
+        decision,
+        tags,
+    })))
+}
+
+fn store_file(
+    db: &Database,
+    fingerprint: &FileFingerprint,
+    status: FileStatus,
+    resolution: Result<Resolution, FileFailure>,
+) -> Result<FileOutcome, String> {
+    let path_str = &fingerprint.path;
+    let Resolution {
+        track,
+        candidates,
+        cover,
+        remote_failure,
+    } = match resolution {
+        Ok(resolution) => resolution,
+        Err(file_failure) => {
+            // Sans correspondance, l'empreinte évite de réanalyser le fichier tant qu'il ne change pas
+            let mut conn = db.connection();
+            let tx = conn.transaction().map_err(|err| err.to_string())?;
+            save_fingerprint(&tx, fingerprint)
+                .and_then(|_| mark_retry(&tx, path_str, false))
+                .and_then(|_| remove_track(&tx, path_str))
+                .and_then(|_| link_track(&tx, path_str, None))
+                .and_then(|_| save_candidates(&tx, path_str, &[]))
+                .and_then(|_| tx.commit())
+                .map_err(|err| err.to_string())?;
+            return Ok(FileOutcome::Failed(file_failure));
+        }
     };
 
+    // Après une erreur Spotify, le fichier sera réanalysé même inchangé
+    let mut conn = db.connection();
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    save_fingerprint(&tx, fingerprint)
+        .and_then(|_| mark_retry(&tx, path_str, remote_failure.is_some()))
+        .and_then(|_| save_track(&tx, &track))
+        .and_then(|_| link_track(&tx, path_str, Some(&track.id)))
+        .and_then(|_| save_candidates(&tx, path_str, &candidates))
+        .and_then(|_| match &cover {
+            Some(cover) => save_album_cover(&tx, &track.album.id, cover),
+            None => Ok(()),
+        })
+        .and_then(|_| tx.commit())
+        .map_err(|err| err.to_string())?;
+
+    if let Some(file_failure) = remote_failure {
+        return Ok(FileOutcome::Failed(file_failure));
+    }
+
+    Ok(FileOutcome::Indexed {
+        track: Box::new(track),
+        status,
+        needs_review: !candidates.is_empty(),
+    })
+}
+
+fn record_outcome(report: &mut ScanReport, outcome: FileOutcome) {
+    match outcome {
+        FileOutcome::Unchanged => report.unchanged += 1,
+        FileOutcome::Indexed {
+            status,
+            needs_review,
+            ..
+        } => {
+            if needs_review {
+                report.needs_review += 1;
+            }
+            match status {
+                FileStatus::Added => report.added += 1,
+                FileStatus::Changed => report.changed += 1,
+                FileStatus::Unchanged => report.unchanged += 1,
+            }
+        }
+        FileOutcome::Failed(file_failure) => report.failures.push(file_failure),
+    }
+}
+
+// Copie locale des images d'albums Spotify ; un échec sera retenté à la prochaine analyse
+async fn cache_artwork(
+    db: &Database,
+    spotify: &SpotifyClient,
+    handle: &ScanHandle,
+) -> Result<(), String> {
+    let missing = blocking({
+        let db = db.clone();
+        move || missing_album_images(&db.connection()).map_err(|err| err.to_string())
+    })
+    .await?;
+
+    for (album_id, position, url) in missing {
+        if handle.is_cancelled() {
+            return Err("Scan cancelled".to_string());
+        }
+
+        let data = match spotify.download(&url).await {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("{}: {}", url, err);
+                continue;
+            }
+        };
+        let db = db.clone();
+        blocking(move || match cover_from(data) {
+            Some(cover) => save_album_image(&db.connection(), &album_id, position, &cover)
+                .map_err(|err| err.to_string()),
+            None => {
+                eprintln!("{}: not an image", url);
+                Ok(())
+            }
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Les listes M3U et XSPF de la bibliothèque sont importées une fois les titres indexés
+fn import_playlists(
+    db: &Database,
+    playlist_files: &[PathBuf],
+    report: &mut ScanReport,
+) -> Result<(), String> {
+    for file_path in playlist_files {
+        let source = file_path.to_string_lossy().into_owned();
+        let file = match read_playlist_file(file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                report
+                    .failures
+                    .push(failure(&source, FailureReason::ReadError, err));
+                continue;
+            }
+        };
+
+        let base_dir = file_path.parent().unwrap_or(Path::new(""));
+        let mut conn = db.connection();
+        let tx = conn.transaction().map_err(|err| err.to_string())?;
+        let mut track_ids = Vec::new();
+        let mut unresolved = Vec::new();
+        for entry in &file.entries {
+            let path = resolve_entry(&file, base_dir, entry)
+                .map(|path| path.canonicalize().unwrap_or(path));
+            let track_id = match &path {
+                Some(path) => track_id_for_path(&tx, &path.to_string_lossy())
+                    .map_err(|err| err.to_string())?,
+                None => None,
+            };
+            match track_id {
+                Some(track_id) => track_ids.push(track_id),
+                None => unresolved.push(entry.clone()),
+            }
+        }
+
+        let name = file
+            .title
+            .clone()
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| {
+                file_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+        let playlist_id = save_imported_playlist(&tx, &source, &name, &track_ids)
+            .map_err(|err| err.to_string())?;
+        tx.commit().map_err(|err| err.to_string())?;
+
+        report.playlists.push(PlaylistImport {
+            path: source,
+            playlist_id,
+            tracks: track_ids.len(),
+            unresolved,
+        });
+    }
+
+    Ok(())
+}
+
+// Fichiers audio, listes de lecture, et dossiers ou liens illisibles
+type LibraryFiles = (Vec<PathBuf>, Vec<PathBuf>, Vec<FileFailure>);
+
+fn list_files(library: &Library, dir: &Path) -> Result<LibraryFiles, String> {
+    let mut files = Vec::new();
+    let mut playlist_files = Vec::new();
+    let mut unreadable_dirs = Vec::new();
     let mut stack: VecDeque<PathBuf> = VecDeque::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
     stack.push_back(dir.to_path_buf());
-
-    let mut entry_counter = 0;
+    visited.insert(dir.to_path_buf());
 
     while let Some(current_dir) = stack.pop_back() {
-        if let Ok(entries) = fs::read_dir(&current_dir) {
-            for entry_result in entries {
-                if let Ok(entry) = entry_result {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        stack.push_back(path.to_path_buf());
-                    } else {
-                        match get_track_data(&path).await {
-                            Some(mut track_data) => {
-                                let album_id = &track_data.album.id;
-                            
-                                // Albums
-                                if !data.albums.iter().any(|a| a.id == album_id.to_string()) {
-                                    if !track_data.album.items.iter().any(|i| i.id == track_data.id) {
-                                        track_data.album.artist = track_data.artist.clone();
-                                        track_data.album.items.push(track_data.clone());
-                                    }
-                            
-                                    // Ajoute l'album à tous les artistes associés
-                                    for artist in &mut track_data.artists {
-                                        if artist.name == track_data.artist {
-                                            if let Some(existing_artist) = data.artists.iter_mut().find(|a| a.id == artist.id) {
-                                                if !existing_artist.albums.iter().any(|a| a.id == album_id.to_string()) {
-                                                    existing_artist.albums.push(track_data.album.clone());
-                                                }
-                                            }
-                                        } else {
-                                            let mut new_artist = artist.clone();
-                                            if !new_artist.albums.iter().any(|a| a.id == album_id.to_string()) {
-                                                new_artist.albums.push(track_data.album.clone());
-                                            }
-                                            if !track_data.album.artists.iter().any(|a| a.id == artist.id) {
-                                                track_data.album.artists.push(new_artist.clone());
-                                            }
-                                            data.artists.push(new_artist.clone());
-                                        }
-                                    }
-                            
-                                    // Ajoute l'album à la liste des albums de Data
-                                    data.albums.push(track_data.album.clone());
-                                }
-                            
-                                data.tracks.push(track_data);
-                            }                            
-                            None => {
-                                return Some(Err(format!(
-                                    "No track data found for file: {:?}",
-                                    &path
-                                )));
-                            }
-                        }
-                    }
+        let entries = match fs::read_dir(&current_dir) {
+            Ok(entries) => entries,
+            Err(_) if current_dir == dir => {
+                return Err(format!("Error reading directory: {:?}", &current_dir))
+            }
+            Err(err) => {
+                unreadable_dirs.push(failure(
+                    &current_dir.to_string_lossy(),
+                    FailureReason::ReadError,
+                    err.to_string(),
+                ));
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            // Liens symboliques résolus : un lien qui sort du dossier est signalé et ignoré
+            let path = match library.confine(&entry.path()) {
+                Ok(path) => path,
+                Err(err) => {
+                    unreadable_dirs.push(failure(
+                        &entry.path().to_string_lossy(),
+                        FailureReason::ReadError,
+                        err.to_string(),
+                    ));
+                    continue;
                 }
+            };
 
-                entry_counter += 1;
-                if entry_counter % 1 == 0 {
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+            if path.is_dir() {
+                // Un lien vers un dossier parent ne fait pas boucler l'analyse
+                if visited.insert(path.clone()) {
+                    stack.push_back(path);
                 }
+            } else if !library.contains(&path) {
+                continue;
+            } else if path.extension().is_some_and(is_audio_file) {
+                files.push(path);
+            } else if path.extension().is_some_and(is_playlist_file) {
+                playlist_files.push(path);
             }
-        } else {
-            return Some(Err(format!("Error reading directory: {:?}", &current_dir)));
-        }
-    }
-
-    Some(Ok(data))
-}
-
-async fn get_track_data(file_path: &Path) -> Option<Item> {
-    if let Ok(tag) = Tag::new().read_from_path(file_path) {
-        let path = file_path.to_string_lossy().into_owned();
-
-        if let Some(extension) = file_path.extension() {
-            if is_audio_file(extension) {
-                let artist = normalize(tag.artist().or(tag.artist()).unwrap_or(""));
-                let title = normalize(tag.title().unwrap_or(""));
-
-                let search_query = format!("search?q={} {}&type=track&limit=1", artist, title);
-                println!("{}", search_query);
-
-                match send_spotify_request(Method::GET, &search_query).await {
-                    Ok(body) => match serde_json::from_str::<SpotifySearchResponse>(&body) {
-                        Ok(parsed_result) => {
-                            match parsed_result.tracks.items.iter().find(|track| {
-                                let lowercase_artist = artist.to_lowercase();
-                                track.artists.iter().any(|a| {
-                                    a.name.to_lowercase() == lowercase_artist
-                                        || lowercase_artist.contains(&a.name.to_lowercase())
-                                }) && title.to_lowercase().contains(&track.name.to_lowercase())
-                            }) {
-                                Some(track) => {
-                                    let mut t = track.clone();
-                                    t.artist = artist;
-                                    t.path = path;
-                                    return Some(t);
-                                }
-                                None => {
-                                    if parsed_result.tracks.items[0].name.contains("Dans") {
-                                        println!(
-                                            "Title found: {} , current title: {}",
-                                            parsed_result.tracks.items[0].name, title
-                                        );
-                                    }
-                                    println!("No track found in the Spotify search response for file: {:?}", file_path);
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            if err.classify() == serde_json::error::Category::Data {
-                                println!("Error parsing JSON data: {}", err);
-                            } else {
-                                println!("Error parsing JSON response: {}", err);
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        println!("Error fetching data from Spotify API: {}", err);
-                    }
-                }
+        }
+    }
+
+    Ok((files, playlist_files, unreadable_dirs))
+}
+
+// La taille et la date de modification évitent de relire le fichier, le hash confirme un vrai changement
+fn file_fingerprint(
+    path: &Path,
+    previous: Option<&FileFingerprint>,
+) -> io::Result<(FileFingerprint, FileStatus)> {
+    let metadata = fs::metadata(path)?;
+    let size = metadata.len() as i64;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+
+    let (hash, status) = match previous {
+        Some(previous) if previous.size == size && previous.mtime == mtime => {
+            (previous.hash.clone(), FileStatus::Unchanged)
+        }
+        Some(previous) => {
+            let hash = hash_file(path)?;
+            let status = if hash == previous.hash {
+                FileStatus::Unchanged
             } else {
-                println!("Error: This is not an audio file");
+                FileStatus::Changed
+            };
+            (hash, status)
+        }
+        None => (hash_file(path)?, FileStatus::Added),
+    };
+
+    Ok((
+        FileFingerprint {
+            path: path.to_string_lossy().into_owned(),
+            size,
+            mtime,
+            hash,
+        },
+        status,
+    ))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Titre à indexer, candidats Spotify à revoir quand aucun n'est assez sûr, et pochette trouvée
+struct Resolution {
+    track: Item,
+    candidates: Vec<MatchCandidate>,
+    cover: Option<Cover>,
+    remote_failure: Option<FileFailure>,
+}
+
+async fn match_track(
+    spotify: &SpotifyClient,
+    path: String,
+    tags: TrackTags,
+    decision: Option<&MatchDecision>,
+) -> Result<(Item, Vec<MatchCandidate>), FileFailure> {
+    // Une décision de la revue manuelle remplace la recherche
+    match decision {
+        Some(MatchDecision::Spotify(track)) => {
+            return Ok((decided_track(track, &tags.artist, &path), Vec::new()))
+        }
+        Some(MatchDecision::Local) => return Ok((local_track(&tags, &path), Vec::new())),
+        None if tags.untagged || !spotify.is_configured() => {
+            return Ok((local_track(&tags, &path), Vec::new()))
+        }
+        None => {}
+    }
+
+    // L'ISRC identifie l'enregistrement : on choisit la sortie la plus proche des tags,
+    // mais des tags trop éloignés laissent la décision à la revue
+    if let Some(isrc) = &tags.isrc {
+        let results = search(spotify, &path, &format!("isrc:{}", isrc)).await?;
+        let mut candidates = score_candidates(&tags, &results);
+        match candidates.first() {
+            Some(best) if best.score >= MATCH_THRESHOLD => {
+                let mut t = best.track.clone();
+                t.artist = tags.artist;
+                t.path = path;
+                t.match_confidence = Some(best.score);
+                t.match_method = Some(MatchMethod::Isrc);
+                return Ok((keyed_by_file(t), Vec::new()));
             }
-        } else {
-            println!("Error: Wrong path");
+            Some(_) => {
+                candidates.truncate(REVIEW_CANDIDATES);
+                return Ok((local_track(&tags, &path), candidates));
+            }
+            None => {}
+        }
+    }
+
+    // Sans ISRC, ou s'il est inconnu de Spotify, recherche par artiste et titre
+    let results = search(spotify, &path, &format!("{} {}", tags.artist, tags.title)).await?;
+    let mut candidates = score_candidates(&tags, &results);
+    match candidates.first() {
+        Some(best) if best.score >= MATCH_THRESHOLD => {
+            let mut t = best.track.clone();
+            t.artist = tags.artist;
+            t.path = path;
+            t.match_confidence = Some(best.score);
+            t.match_method = Some(MatchMethod::Search);
+            Ok((keyed_by_file(t), Vec::new()))
         }
-    } else {
-        println!("Error: Path not found");
+        _ => {
+            candidates.truncate(REVIEW_CANDIDATES);
+            Ok((local_track(&tags, &path), candidates))
+        }
+    }
+}
+
+async fn search(
+    spotify: &SpotifyClient,
+    path: &str,
+    query: &str,
+) -> Result<Vec<Item>, FileFailure> {
+    let search_query = format!(
+        "search?q={}&type=track&limit={}",
+        utf8_percent_encode(query, NON_ALPHANUMERIC),
+        SEARCH_LIMIT
+    );
+
+    let body = match spotify.send(Method::GET, &search_query).await {
+        Ok(body) => body,
+        Err(err) => return Err(failure(path, FailureReason::RemoteError, err.to_string())),
+    };
+
+    match serde_json::from_str::<SpotifySearchResponse>(&body) {
+        Ok(parsed_result) => Ok(parsed_result.tracks.items),
+        Err(err) => Err(failure(
+            path,
+            FailureReason::RemoteError,
+            format!("Error parsing JSON response: {}", err),
+        )),
+    }
+}
+
+fn failure(path: &str, reason: FailureReason, message: impl Into<String>) -> FileFailure {
+    FileFailure {
+        path: path.to_string(),
+        reason,
+        message: message.into(),
     }
-    None
 }
 
 fn is_audio_file(extension: &std::ffi::OsStr) -> bool {
-    let audio_extensions = ["mp3", "wav", "flac", "aac", "ogg", "wma"];
-    audio_extensions.contains(&extension.to_string_lossy().to_lowercase().as_str())
+    audio_content_type(extension).is_some()
 }
 
-fn normalize(title: &str) -> String {
-    title.replace("\\", "")
+// Type MIME servi pour chaque format audio reconnu par l'analyse
+pub fn audio_content_type(extension: &std::ffi::OsStr) -> Option<&'static str> {
+    match extension.to_string_lossy().to_lowercase().as_str() {
+        "mp3" => Some("audio/mpeg"),
+        "wav" => Some("audio/wav"),
+        "flac" => Some("audio/flac"),
+        "aac" => Some("audio/aac"),
+        "m4a" | "mp4" => Some("audio/mp4"),
+        "ogg" => Some("audio/ogg"),
+        "wma" => Some("audio/x-ms-wma"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockSpotify;
+    use crate::api::spotify::SpotifyConfig;
+    use crate::data::libraries::Library;
+    use crate::data::models::{Page, TrackFilter};
+    use crate::database::library::{load_albums, load_artists, load_tracks};
+    use crate::database::matches::load_candidates;
+    use crate::database::playlists::{load_playlist, save_entries, update_playlist};
+    use audiotags::{AudioTagEdit, AudioTagWrite, Id3v2Tag};
+    use id3::TagLike;
+
+    fn tagged_file(dir: &Path, name: &str, artist: &str, title: &str, album: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, b"").unwrap();
+
+        let mut tag = Id3v2Tag::new();
+        tag.set_artist(artist);
+        tag.set_title(title);
+        tag.set_album_title(album);
+        tag.write_to_path(path.to_str().unwrap()).unwrap();
+
+        path
+    }
+
+    fn under(dir: &str) -> TrackFilter {
+        TrackFilter {
+            path: dir.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn scan_matches_spotify_and_keeps_local_tracks() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+
+        tagged_file(
+            dir.path(),
+            "01.mp3",
+            "Daft Punk",
+            "One More Time",
+            "Discovery",
+        );
+        let local = tagged_file(dir.path(), "02.mp3", "Garage Band", "First Demo", "Demos");
+        tagged_file(
+            dir.path(),
+            "03.mp3",
+            "Daft Punk",
+            "Unreleased Jam",
+            "Bootlegs",
+        );
+        fs::write(dir.path().join("cover.jpg"), b"jpg").unwrap();
+
+        let report = get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.added, 3);
+        assert_eq!(report.needs_review, 1);
+        assert!(report.failures.is_empty());
+        let albums = load_albums(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .1;
+        assert_eq!(albums, 3);
+
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        let matched = tracks.iter().find(|t| !t.is_local).unwrap();
+        assert_eq!(
+            matched.spotify_id.as_deref(),
+            Some("0DiWol3AO6WpXZgp0goxAV")
+        );
+        assert_eq!(matched.album.name, "Discovery");
+        assert!(matched.match_confidence.unwrap() >= MATCH_THRESHOLD);
+        assert_eq!(matched.match_method, Some(MatchMethod::Search));
+        let local_track = tracks.iter().find(|t| t.name == "First Demo").unwrap();
+        assert!(local_track.is_local);
+        assert_eq!(local_track.name, "First Demo");
+        assert_eq!(local_track.album.artists[0].name, "Garage Band");
+
+        // Rien n'a changé : aucune nouvelle recherche Spotify
+        let searches = mock.searches().len();
+        let report = get_tracks_data(&db, &spotify, &library, true, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, 3);
+        assert_eq!(mock.searches().len(), searches);
+
+        fs::remove_file(local).unwrap();
+        let report = get_tracks_data(&db, &spotify, &library, true, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(report.removed, 1);
+
+        let artists = load_artists(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        assert!(artists.iter().all(|artist| artist.name != "Garage Band"));
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_caches_spotify_album_images() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+        tagged_file(
+            dir.path(),
+            "01.mp3",
+            "Daft Punk",
+            "One More Time",
+            "Discovery",
+        );
+
+        get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        let album = &tracks[0].album;
+        assert!(!album.images.is_empty());
+        for (position, image) in album.images.iter().enumerate() {
+            assert_eq!(
+                image.url,
+                format!("/albums/{}/images/{}", album.id, position)
+            );
+            assert!(image.fallback_url.as_ref().unwrap().contains("/image/"));
+        }
+        let downloads = mock.image_downloads();
+        assert_eq!(downloads, album.images.len());
+
+        // Images déjà en cache : aucun nouveau téléchargement
+        get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(mock.image_downloads(), downloads);
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_keeps_every_file_of_the_same_spotify_track() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+
+        // Même titre en deux exemplaires, associés à la même correspondance Spotify
+        for name in ["01.mp3", "copy.mp3"] {
+            tagged_file(dir.path(), name, "Daft Punk", "One More Time", "Discovery");
+        }
+
+        let report = get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(report.added, 2);
+
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        assert_eq!(tracks.len(), 2);
+        assert_ne!(tracks[0].id, tracks[1].id);
+        for track in &tracks {
+            assert_eq!(track.spotify_id.as_deref(), Some("0DiWol3AO6WpXZgp0goxAV"));
+        }
+    }
+
+    #[actix_web::test]
+    async fn scan_keeps_untagged_formats_as_local_tracks() {
+        let mock = MockSpotify::start().await;
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+        let album = dir.path().join("Live 1999");
+        fs::create_dir(&album).unwrap();
+        for name in ["01 Intro.ogg", "02 Encore.wma", "03 Outro.aac"] {
+            fs::write(album.join(name), b"audio").unwrap();
+        }
+
+        let report = get_tracks_data(&db, &mock.client(), &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.added, 3);
+        assert!(report.failures.is_empty());
+        assert!(mock.searches().is_empty());
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        assert!(tracks.iter().all(|track| track.is_local));
+        assert!(tracks.iter().any(|track| track.name == "02 Encore"));
+        assert!(tracks.iter().all(|track| track.album.name == "Live 1999"));
+        assert_eq!(tracks[0].album.id, tracks[2].album.id);
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_looks_up_isrc_before_searching_text() {
+        let mock = MockSpotify::start().await;
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+        let with_isrc = |path: &Path, isrc: &str| {
+            let mut tag = id3::Tag::read_from_path(path).unwrap();
+            tag.add_frame(id3::Frame::text("TSRC", isrc));
+            tag.write_to_path(path, id3::Version::Id3v24).unwrap();
+        };
+
+        let path = tagged_file(
+            dir.path(),
+            "04.mp3",
+            "Daft Punk",
+            "Harder Better Faster Stronger",
+            "",
+        );
+        with_isrc(&path, "GB-DUW-00-00059");
+
+        // Tags sans rapport avec l'enregistrement trouvé : la revue décide
+        let path = tagged_file(dir.path(), "05.mp3", "Unknown", "Track 05", "");
+        with_isrc(&path, "GBDUW0000053");
+
+        let report = get_tracks_data(&db, &mock.client(), &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        let mut searches = mock.searches();
+        searches.sort();
+        assert_eq!(searches, vec!["isrc:GBDUW0000053", "isrc:GBDUW0000059"]);
+        assert_eq!(report.needs_review, 1);
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        let matched = tracks.iter().find(|t| !t.is_local).unwrap();
+        assert_eq!(
+            matched.spotify_id.as_deref(),
+            Some("5W3cjX2J3tjhG8zb6u0qHn")
+        );
+        assert_eq!(matched.match_method, Some(MatchMethod::Isrc));
+        assert!(matched.match_confidence.unwrap() >= MATCH_THRESHOLD);
+
+        let candidates = load_candidates(&db.connection(), &path.to_string_lossy()).unwrap();
+        assert_eq!(candidates[0].track.id, "0DiWol3AO6WpXZgp0goxAV");
+        assert!(candidates[0].score < MATCH_THRESHOLD);
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_imports_playlist_files() {
+        let mock = MockSpotify::start().await;
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let album = dir.path().join("Demos");
+        fs::create_dir(&album).unwrap();
+
+        let track = tagged_file(&album, "01.mp3", "Garage Band", "First Demo", "Demos");
+        let playlist = dir.path().join("Favourites.m3u");
+        fs::write(
+            &playlist,
+            "#EXTM3U\n#EXTINF:12,Garage Band - First Demo\nDemos/01.mp3\nDemos/missing.mp3\n",
+        )
+        .unwrap();
+
+        let report = get_tracks_data(&db, &mock.client(), &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        let import = &report.playlists[0];
+        assert_eq!(import.tracks, 1);
+        assert_eq!(import.unresolved, vec!["Demos/missing.mp3".to_string()]);
+        let mut imported =
+            load_playlist(&db.connection(), &import.playlist_id, &[library.prefix()])
+                .unwrap()
+                .unwrap();
+        assert_eq!(imported.name, "Favourites");
+        assert_eq!(imported.tracks[0].path, track.to_string_lossy());
+
+        // Liste renommée et vidée depuis l'API : le fichier ne l'écrase plus
+        imported.name = "Road Trip".to_string();
+        update_playlist(&db.connection(), &imported).unwrap();
+        save_entries(&db.connection(), &imported.id, &[]).unwrap();
+        get_tracks_data(&db, &mock.client(), &library, true, &ScanHandle::default())
+            .await
+            .unwrap();
+        let kept = load_playlist(&db.connection(), &import.playlist_id, &[library.prefix()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.name, "Road Trip");
+        assert!(kept.track_ids.is_empty());
+
+        // Fichier supprimé : seules les listes jamais modifiées disparaissent
+        let other = dir.path().join("Demos.m3u");
+        fs::write(&other, "Demos/01.mp3\n").unwrap();
+        let report = get_tracks_data(&db, &mock.client(), &library, true, &ScanHandle::default())
+            .await
+            .unwrap();
+        let other_id = report
+            .playlists
+            .iter()
+            .find(|import| import.path == "Demos.m3u")
+            .unwrap()
+            .playlist_id
+            .clone();
+        fs::remove_file(&playlist).unwrap();
+        fs::remove_file(&other).unwrap();
+        get_tracks_data(&db, &mock.client(), &library, true, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert!(
+            load_playlist(&db.connection(), &import.playlist_id, &[library.prefix()])
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            load_playlist(&db.connection(), &other_id, &[library.prefix()])
+                .unwrap()
+                .is_none()
+        );
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn updates_only_touch_the_changed_paths() {
+        let mock = MockSpotify::start().await;
+        let spotify = mock.client();
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+
+        tagged_file(
+            dir.path(),
+            "01.mp3",
+            "Daft Punk",
+            "One More Time",
+            "Discovery",
+        );
+        get_tracks_data(&db, &spotify, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+        let searches = mock.searches().len();
+
+        // Nouvel album déposé : seul son titre est résolu
+        let album = dir.path().join("Demos");
+        fs::create_dir(&album).unwrap();
+        tagged_file(&album, "01.mp3", "Garage Band", "First Demo", "Demos");
+        let report = update_paths(&db, &spotify, &library, std::slice::from_ref(&album))
+            .await
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(mock.searches().len(), searches + 1);
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        assert_eq!(tracks.len(), 2);
+
+        // Dossier renommé : l'ancien chemin disparaît, le nouveau est indexé
+        let renamed = dir.path().join("Demo Tape");
+        fs::rename(&album, &renamed).unwrap();
+        let report = update_paths(&db, &spotify, &library, &[album, renamed.clone()])
+            .await
+            .unwrap();
+        assert_eq!((report.removed, report.added), (1, 1));
+        let tracks = load_tracks(
+            &db.connection(),
+            &under(&renamed.to_string_lossy()),
+            &Page::default(),
+        )
+        .unwrap()
+        .0;
+        assert_eq!(tracks[0].name, "First Demo");
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_reports_unreadable_files_and_continues() {
+        let mock = MockSpotify::start().await;
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+
+        fs::write(dir.path().join("broken.mp3"), b"not an mp3").unwrap();
+        tagged_file(
+            dir.path(),
+            "01.mp3",
+            "Daft Punk",
+            "One More Time",
+            "Discovery",
+        );
+
+        // Un lien vers un fichier extérieur est refusé, un lien vers le dossier lui-même ne boucle pas
+        let outside = tempfile::tempdir().unwrap();
+        let secret = tagged_file(outside.path(), "secret.mp3", "Air", "Secret", "Outside");
+        std::os::unix::fs::symlink(&secret, dir.path().join("linked.mp3")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("loop")).unwrap();
+
+        let report = get_tracks_data(&db, &mock.client(), &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.added, 1);
+        assert_eq!(report.failures.len(), 2);
+        let failure = |path: &str| {
+            report
+                .failures
+                .iter()
+                .find(|failure| failure.path == path)
+                .unwrap()
+        };
+        assert_eq!(failure("broken.mp3").reason, FailureReason::NoTags);
+        assert_eq!(failure("linked.mp3").message, "Outside of the library");
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn scan_indexes_local_tracks_without_spotify() {
+        let mock = MockSpotify::start().await;
+        let db = Database::open(":memory:").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::at(dir.path());
+        let dir_str = dir.path().to_string_lossy().into_owned();
+        tagged_file(
+            dir.path(),
+            "01.mp3",
+            "Daft Punk",
+            "One More Time",
+            "Discovery",
+        );
+
+        // Sans identifiants, Spotify n'est pas sollicité
+        let unconfigured = SpotifyClient::new(SpotifyConfig {
+            client_id: String::new(),
+            client_secret: String::new(),
+            ..mock.config()
+        });
+        let report = get_tracks_data(&db, &unconfigured, &library, false, &ScanHandle::default())
+            .await
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert!(report.failures.is_empty());
+        assert_eq!(mock.token_requests(), 0);
+        assert!(mock.searches().is_empty());
+
+        // Spotify injoignable : le titre reste local et l'analyse suivante le retente
+        let unreachable = SpotifyClient::new(SpotifyConfig {
+            api_url: "http://127.0.0.1:1/v1/".to_string(),
+            token_url: "http://127.0.0.1:1/api/token".to_string(),
+            ..mock.config()
+        });
+        for changed_only in [false, true] {
+            let report = get_tracks_data(
+                &db,
+                &unreachable,
+                &library,
+                changed_only,
+                &ScanHandle::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(report.failures.len(), 1);
+            assert_eq!(report.failures[0].reason, FailureReason::RemoteError);
+        }
+        let tracks = load_tracks(&db.connection(), &under(&dir_str), &Page::default())
+            .unwrap()
+            .0;
+        assert_eq!(tracks.len(), 1);
+        assert!(tracks[0].is_local);
+        mock.stop().await;
+    }
 }
@@ -0,0 +1,203 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use super::jobs::ScanJobs;
+use super::libraries::Libraries;
+use super::utils::update_paths;
+use crate::api::spotify::SpotifyClient;
+use crate::database::database::Database;
+
+const DEFAULT_DEBOUNCE_MS: u64 = 2000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    pub enabled: bool,
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> WatchConfig {
+        WatchConfig {
+            enabled: false,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+        }
+    }
+}
+
+// Surveille les dossiers activés ; la surveillance s'arrête quand le watcher retourné est libéré
+pub fn watch(
+    config: &WatchConfig,
+    libraries: Libraries,
+    db: Database,
+    spotify: SpotifyClient,
+    scan_jobs: ScanJobs,
+) -> notify::Result<RecommendedWatcher> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if is_change(&event.kind) => {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => println!("{}", err),
+        })?;
+
+    for library in libraries.enabled() {
+        watcher.watch(&library.path, RecursiveMode::Recursive)?;
+        println!("Watching {}", library.path.display());
+    }
+
+    actix_web::rt::spawn(apply_changes(
+        receiver,
+        Duration::from_millis(config.debounce_ms),
+        libraries,
+        db,
+        spotify,
+        scan_jobs,
+    ));
+    Ok(watcher)
+}
+
+// Les renommages arrivent comme des modifications, avec l'ancien et le nouveau chemin
+fn is_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+// Un dossier en cours d'analyse n'est pas modifié en parallèle : ses chemins sont gardés pour le lot suivant
+async fn apply_changes(
+    mut receiver: UnboundedReceiver<PathBuf>,
+    debounce: Duration,
+    libraries: Libraries,
+    db: Database,
+    spotify: SpotifyClient,
+    scan_jobs: ScanJobs,
+) {
+    let mut pending = Vec::new();
+    while let Some(paths) = next_batch(&mut receiver, debounce, pending).await {
+        pending = Vec::new();
+        for library in libraries.enabled() {
+            let paths: Vec<PathBuf> = paths
+                .iter()
+                .filter(|path| {
+                    libraries
+                        .containing(path)
+                        .is_some_and(|containing| containing.id == library.id)
+                })
+                .cloned()
+                .collect();
+            if paths.is_empty() {
+                continue;
+            }
+            let Some(_reservation) = scan_jobs.reserve(&library.id) else {
+                pending.extend(paths);
+                continue;
+            };
+
+            match update_paths(&db, &spotify, library, &paths).await {
+                Ok(report) => println!(
+                    "{} updated: {} added, {} changed, {} removed, {} failed",
+                    library.name,
+                    report.added,
+                    report.changed,
+                    report.removed,
+                    report.failures.len()
+                ),
+                Err(err) => println!("{}", err),
+            }
+        }
+    }
+}
+
+// Chemins reçus jusqu'à `debounce` sans nouvel événement, en plus de ceux restés en attente ;
+// un dossier couvre les chemins qu'il contient
+async fn next_batch(
+    receiver: &mut UnboundedReceiver<PathBuf>,
+    debounce: Duration,
+    pending: Vec<PathBuf>,
+) -> Option<Vec<PathBuf>> {
+    let mut paths: BTreeSet<PathBuf> = pending.into_iter().collect();
+    if paths.is_empty() {
+        paths.insert(receiver.recv().await?);
+    }
+
+    while let Ok(Some(path)) = tokio::time::timeout(debounce, receiver.recv()).await {
+        paths.insert(path);
+    }
+
+    let mut batch: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !batch.iter().any(|parent| path.starts_with(parent)) {
+            batch.push(path);
+        }
+    }
+
+    Some(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn bursts_are_merged_into_one_batch() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let debounce = Duration::from_millis(50);
+
+        for path in [
+            "/music/Album/02.mp3",
+            "/music/Album",
+            "/music/Album/01.mp3",
+            "/music/Other/01.mp3",
+            "/music/Other/01.mp3",
+        ] {
+            sender.send(PathBuf::from(path)).unwrap();
+        }
+        let batch = next_batch(&mut receiver, debounce, Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            batch,
+            vec![
+                PathBuf::from("/music/Album"),
+                PathBuf::from("/music/Other/01.mp3")
+            ]
+        );
+
+        // Un événement après le silence ouvre un nouveau lot
+        sender.send(PathBuf::from("/music/Late.mp3")).unwrap();
+        drop(sender);
+        let batch = next_batch(&mut receiver, debounce, Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(batch, vec![PathBuf::from("/music/Late.mp3")]);
+        assert!(next_batch(&mut receiver, debounce, Vec::new())
+            .await
+            .is_none());
+    }
+
+    #[actix_web::test]
+    async fn pending_paths_do_not_wait_for_new_events() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let debounce = Duration::from_millis(50);
+
+        let pending = vec![PathBuf::from("/music/Album/01.mp3")];
+        let batch = next_batch(&mut receiver, debounce, pending.clone())
+            .await
+            .unwrap();
+        assert_eq!(batch, pending);
+
+        sender.send(PathBuf::from("/music/Album")).unwrap();
+        let batch = next_batch(&mut receiver, debounce, pending).await.unwrap();
+        assert_eq!(batch, vec![PathBuf::from("/music/Album")]);
+    }
+}
@@ -0,0 +1,134 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use super::library::within_sql;
+use crate::data::covers::Cover;
+
+// Une même image n'est stockée qu'une fois, quel que soit le nombre d'albums qui l'utilisent
+pub fn save_album_cover(conn: &Connection, album_id: &str, cover: &Cover) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO covers (hash, mime_type, data) VALUES (?1, ?2, ?3)",
+        params![cover.hash, cover.mime_type, cover.data],
+    )?;
+    conn.execute(
+        "INSERT INTO album_covers (album_id, hash) VALUES (?1, ?2)
+         ON CONFLICT(album_id) DO UPDATE SET hash = excluded.hash",
+        params![album_id, cover.hash],
+    )?;
+
+    Ok(())
+}
+
+// Pochette d'un album ayant un titre sous un des préfixes
+pub fn load_album_cover(
+    conn: &Connection,
+    album_id: &str,
+    prefixes: &[String],
+) -> rusqlite::Result<Option<Cover>> {
+    let mut params = vec![Value::Text(album_id.to_string())];
+    let within = within_sql(prefixes, &mut params);
+    conn.query_row(
+        &format!(
+            "SELECT covers.hash, covers.mime_type, covers.data FROM album_covers
+             JOIN covers ON covers.hash = album_covers.hash
+             WHERE album_covers.album_id = ?1
+             AND EXISTS (SELECT 1 FROM tracks WHERE tracks.album_id = ?1 AND {})",
+            within
+        ),
+        params_from_iter(params),
+        |row| {
+            Ok(Cover {
+                hash: row.get(0)?,
+                mime_type: row.get(1)?,
+                data: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub fn load_thumbnail(
+    conn: &Connection,
+    hash: &str,
+    size: u32,
+    format: &str,
+) -> rusqlite::Result<Option<Vec<u8>>> {
+    conn.query_row(
+        "SELECT data FROM cover_thumbnails WHERE hash = ?1 AND size = ?2 AND format = ?3",
+        params![hash, size, format],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn save_thumbnail(
+    conn: &Connection,
+    hash: &str,
+    size: u32,
+    format: &str,
+    data: &[u8],
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO cover_thumbnails (hash, size, format, data) VALUES (?1, ?2, ?3, ?4)",
+        params![hash, size, format, data],
+    )?;
+
+    Ok(())
+}
+
+// Images Spotify pas encore copiées dans le cache local
+pub fn missing_album_images(conn: &Connection) -> rusqlite::Result<Vec<(String, i64, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT album_id, position, url FROM album_images
+         WHERE hash IS NULL ORDER BY album_id, position",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+    rows.collect()
+}
+
+pub fn save_album_image(
+    conn: &Connection,
+    album_id: &str,
+    position: i64,
+    cover: &Cover,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO covers (hash, mime_type, data) VALUES (?1, ?2, ?3)",
+        params![cover.hash, cover.mime_type, cover.data],
+    )?;
+    conn.execute(
+        "UPDATE album_images SET hash = ?3 WHERE album_id = ?1 AND position = ?2",
+        params![album_id, position, cover.hash],
+    )?;
+
+    Ok(())
+}
+
+pub fn load_album_image(
+    conn: &Connection,
+    album_id: &str,
+    position: i64,
+    prefixes: &[String],
+) -> rusqlite::Result<Option<Cover>> {
+    let mut params = vec![Value::Text(album_id.to_string()), Value::Integer(position)];
+    let within = within_sql(prefixes, &mut params);
+    conn.query_row(
+        &format!(
+            "SELECT covers.hash, covers.mime_type, covers.data FROM album_images
+             JOIN covers ON covers.hash = album_images.hash
+             WHERE album_images.album_id = ?1 AND album_images.position = ?2
+             AND EXISTS (SELECT 1 FROM tracks WHERE tracks.album_id = ?1 AND {})",
+            within
+        ),
+        params_from_iter(params),
+        |row| {
+            Ok(Cover {
+                hash: row.get(0)?,
+                mime_type: row.get(1)?,
+                data: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
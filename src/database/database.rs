@@ -1,11 +1,325 @@
-use actix_web::{get, Responder, HttpResponse};
-use serde_json::json;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex, MutexGuard};
 
-#[get("/database")]
-pub async fn get_database() -> impl Responder {
-    let json_response = json!({
-        "message": "Database!",
-    });
-    HttpResponse::Ok().json(json_response)
+use crate::data::tags::track_key;
+
+const DEFAULT_DATABASE_PATH: &str = "library.db";
+
+// Migrations appliquées dans l'ordre, la version courante est stockée dans PRAGMA user_version
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE artists (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        href TEXT NOT NULL,
+        type TEXT NOT NULL,
+        uri TEXT NOT NULL,
+        spotify_url TEXT NOT NULL
+    );
+
+    CREATE TABLE albums (
+        id TEXT PRIMARY KEY,
+        artist TEXT NOT NULL,
+        album_type TEXT NOT NULL,
+        total_tracks INTEGER NOT NULL,
+        available_markets TEXT NOT NULL,
+        spotify_url TEXT NOT NULL,
+        href TEXT NOT NULL,
+        name TEXT NOT NULL,
+        release_date TEXT NOT NULL,
+        release_date_precision TEXT NOT NULL,
+        type TEXT NOT NULL,
+        uri TEXT NOT NULL
+    );
+
+    CREATE TABLE album_images (
+        album_id TEXT NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        height INTEGER NOT NULL,
+        width INTEGER NOT NULL,
+        PRIMARY KEY (album_id, position)
+    );
+
+    CREATE TABLE album_artists (
+        album_id TEXT NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+        artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (album_id, artist_id)
+    );
+
+    CREATE TABLE tracks (
+        id TEXT PRIMARY KEY,
+        album_id TEXT NOT NULL REFERENCES albums(id),
+        artist TEXT NOT NULL,
+        available_markets TEXT NOT NULL,
+        disc_number INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        explicit INTEGER NOT NULL,
+        isrc TEXT NOT NULL,
+        spotify_url TEXT NOT NULL,
+        href TEXT NOT NULL,
+        name TEXT NOT NULL,
+        popularity INTEGER NOT NULL,
+        preview_url TEXT,
+        track_number INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        uri TEXT NOT NULL,
+        is_local INTEGER NOT NULL,
+        path TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE track_artists (
+        track_id TEXT NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+        artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (track_id, artist_id)
+    );
+
+    CREATE INDEX tracks_album_id ON tracks(album_id);
+    CREATE INDEX track_artists_artist_id ON track_artists(artist_id);
+    CREATE INDEX album_artists_artist_id ON album_artists(artist_id);
+    ",
+    "
+    CREATE TABLE files (
+        path TEXT PRIMARY KEY,
+        size INTEGER NOT NULL,
+        mtime INTEGER NOT NULL,
+        hash TEXT NOT NULL,
+        track_id TEXT REFERENCES tracks(id) ON DELETE SET NULL,
+        scanned_at INTEGER NOT NULL
+    );
+    ",
+    "
+    ALTER TABLE tracks ADD COLUMN match_confidence REAL;
+
+    CREATE TABLE match_candidates (
+        path TEXT NOT NULL REFERENCES files(path) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        spotify_id TEXT NOT NULL,
+        score REAL NOT NULL,
+        track TEXT NOT NULL,
+        PRIMARY KEY (path, position)
+    );
+    ",
+    "
+    CREATE TABLE match_decisions (
+        path TEXT PRIMARY KEY REFERENCES files(path) ON DELETE CASCADE,
+        decision TEXT NOT NULL,
+        track TEXT,
+        decided_at INTEGER NOT NULL
+    );
+    ",
+    "ALTER TABLE tracks ADD COLUMN match_method TEXT;",
+    "
+    CREATE TABLE covers (
+        hash TEXT PRIMARY KEY,
+        mime_type TEXT NOT NULL,
+        data BLOB NOT NULL
+    );
+
+    CREATE TABLE album_covers (
+        album_id TEXT PRIMARY KEY REFERENCES albums(id) ON DELETE CASCADE,
+        hash TEXT NOT NULL REFERENCES covers(hash)
+    );
+
+    CREATE TABLE cover_thumbnails (
+        hash TEXT NOT NULL REFERENCES covers(hash) ON DELETE CASCADE,
+        size INTEGER NOT NULL,
+        format TEXT NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (hash, size, format)
+    );
+    ",
+    "ALTER TABLE album_images ADD COLUMN hash TEXT REFERENCES covers(hash);",
+    "
+    CREATE TABLE playlists (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        cover TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE playlist_entries (
+        playlist_id TEXT NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        track_id TEXT NOT NULL,
+        PRIMARY KEY (playlist_id, position)
+    );
+
+    CREATE INDEX playlist_entries_track_id ON playlist_entries(track_id);
+    ",
+    "
+    ALTER TABLE playlists ADD COLUMN source TEXT;
+    CREATE UNIQUE INDEX playlists_source ON playlists(source);
+    ",
+    "
+    ALTER TABLE files ADD COLUMN added_at INTEGER;
+    UPDATE files SET added_at = scanned_at;
+    ALTER TABLE files ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE playlists ADD COLUMN rules TEXT;
+    ",
+    "
+    CREATE TABLE search_items (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        item_id TEXT NOT NULL,
+        UNIQUE (kind, item_id)
+    );
+
+    CREATE VIRTUAL TABLE search_index USING fts5(
+        name, artist, album, year,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+
+    CREATE VIRTUAL TABLE search_terms USING fts5vocab(search_index, row);
+
+    CREATE TRIGGER tracks_unindex AFTER DELETE ON tracks BEGIN
+        DELETE FROM search_index WHERE rowid IN
+            (SELECT id FROM search_items WHERE kind = 'track' AND item_id = old.id);
+        DELETE FROM search_items WHERE kind = 'track' AND item_id = old.id;
+    END;
+
+    CREATE TRIGGER albums_unindex AFTER DELETE ON albums BEGIN
+        DELETE FROM search_index WHERE rowid IN
+            (SELECT id FROM search_items WHERE kind = 'album' AND item_id = old.id);
+        DELETE FROM search_items WHERE kind = 'album' AND item_id = old.id;
+    END;
+
+    CREATE TRIGGER artists_unindex AFTER DELETE ON artists BEGIN
+        DELETE FROM search_index WHERE rowid IN
+            (SELECT id FROM search_items WHERE kind = 'artist' AND item_id = old.id);
+        DELETE FROM search_items WHERE kind = 'artist' AND item_id = old.id;
+    END;
+
+    INSERT INTO search_items (kind, item_id)
+        SELECT 'track', id FROM tracks
+        UNION ALL SELECT 'album', id FROM albums
+        UNION ALL SELECT 'artist', id FROM artists;
+
+    INSERT INTO search_index (rowid, name, artist, album, year)
+        SELECT search_items.id, tracks.name,
+            trim(tracks.artist || ' ' || COALESCE((SELECT group_concat(artists.name, ' ')
+                FROM track_artists JOIN artists ON artists.id = track_artists.artist_id
+                WHERE track_artists.track_id = tracks.id), '')),
+            albums.name, substr(albums.release_date, 1, 4)
+        FROM search_items
+        JOIN tracks ON tracks.id = search_items.item_id
+        JOIN albums ON albums.id = tracks.album_id
+        WHERE search_items.kind = 'track';
+
+    INSERT INTO search_index (rowid, name, artist, album, year)
+        SELECT search_items.id, albums.name,
+            trim(albums.artist || ' ' || COALESCE((SELECT group_concat(artists.name, ' ')
+                FROM album_artists JOIN artists ON artists.id = album_artists.artist_id
+                WHERE album_artists.album_id = albums.id), '')),
+            albums.name, substr(albums.release_date, 1, 4)
+        FROM search_items JOIN albums ON albums.id = search_items.item_id
+        WHERE search_items.kind = 'album';
+
+    INSERT INTO search_index (rowid, name, artist, album, year)
+        SELECT search_items.id, artists.name, artists.name, '', ''
+        FROM search_items JOIN artists ON artists.id = search_items.item_id
+        WHERE search_items.kind = 'artist';
+    ",
+    // Miniatures générées avant les paliers de taille fixes
+    "DELETE FROM cover_thumbnails WHERE size NOT IN (64, 128, 256, 512, 1024);",
+    "ALTER TABLE playlists ADD COLUMN edited INTEGER NOT NULL DEFAULT 0;",
+    // Fichiers indexés localement faute d'avoir pu joindre Spotify
+    "ALTER TABLE files ADD COLUMN retry INTEGER NOT NULL DEFAULT 0;",
+    // Titres identifiés par leur fichier : deux fichiers de la même correspondance Spotify
+    // ne s'écrasent plus, l'id Spotify passe dans sa propre colonne
+    "
+    PRAGMA defer_foreign_keys = ON;
+
+    ALTER TABLE tracks ADD COLUMN spotify_id TEXT;
+    UPDATE tracks SET spotify_id = id WHERE is_local = 0;
+
+    CREATE TEMP TABLE track_keys AS SELECT id AS old_id, track_key(path) AS new_id FROM tracks;
+    UPDATE track_artists SET track_id = (SELECT new_id FROM track_keys WHERE old_id = track_id);
+    UPDATE files SET track_id = (SELECT new_id FROM track_keys WHERE old_id = track_id)
+        WHERE track_id IS NOT NULL;
+    UPDATE playlist_entries SET track_id = (SELECT new_id FROM track_keys WHERE old_id = track_id)
+        WHERE track_id IN (SELECT old_id FROM track_keys);
+    UPDATE search_items SET item_id = (SELECT new_id FROM track_keys WHERE old_id = item_id)
+        WHERE kind = 'track' AND item_id IN (SELECT old_id FROM track_keys);
+    UPDATE tracks SET id = track_key(path);
+    DROP TABLE track_keys;
+
+    CREATE INDEX tracks_spotify_id ON tracks(spotify_id);
+    ",
+];
+
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &str) -> rusqlite::Result<Database> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        // Une requête qui a paniqué ne doit pas bloquer la base pour les suivantes
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig {
+            path: DEFAULT_DATABASE_PATH.to_string(),
+        }
+    }
+}
+
+// Migrations en attente appliquées sans ouvrir la base pour le serveur ; versions avant et après
+pub fn migrate_file(path: &str) -> rusqlite::Result<(i64, i64)> {
+    let mut conn = Connection::open(path)?;
+    let before = schema_version(&conn)?;
+    migrate(&mut conn)?;
+
+    Ok((before, schema_version(&conn)?))
+}
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version = schema_version(conn)?;
+
+    // Clé d'un titre calculée comme à l'indexation, pour les migrations qui la réécrivent
+    conn.create_scalar_function(
+        "track_key",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(track_key(&ctx.get::<String>(0)?)),
+    )?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index as i64 + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
 }
- 
\ No newline at end of file
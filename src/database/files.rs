@@ -0,0 +1,110 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::library::remove_track;
+use crate::data::models::FileFingerprint;
+
+pub fn load_fingerprint(
+    conn: &Connection,
+    path: &str,
+) -> rusqlite::Result<Option<FileFingerprint>> {
+    conn.query_row(
+        "SELECT path, size, mtime, hash FROM files WHERE path = ?1",
+        params![path],
+        |row| {
+            Ok(FileFingerprint {
+                path: row.get(0)?,
+                size: row.get(1)?,
+                mtime: row.get(2)?,
+                hash: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub fn save_fingerprint(conn: &Connection, fingerprint: &FileFingerprint) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO files (path, size, mtime, hash, scanned_at, added_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT(path) DO UPDATE SET
+            size = excluded.size,
+            mtime = excluded.mtime,
+            hash = excluded.hash,
+            scanned_at = excluded.scanned_at",
+        params![
+            fingerprint.path,
+            fingerprint.size,
+            fingerprint.mtime,
+            fingerprint.hash,
+            now(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn link_track(conn: &Connection, path: &str, track_id: Option<&str>) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE files SET track_id = ?2 WHERE path = ?1",
+        params![path, track_id],
+    )?;
+
+    Ok(())
+}
+
+// Fichier à réanalyser même inchangé, quand Spotify n'a pas pu être joint
+pub fn mark_retry(conn: &Connection, path: &str, retry: bool) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE files SET retry = ?2 WHERE path = ?1",
+        params![path, retry],
+    )?;
+
+    Ok(())
+}
+
+pub fn needs_retry(conn: &Connection, path: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT retry FROM files WHERE path = ?1",
+        params![path],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(|retry| retry.unwrap_or(false))
+}
+
+// Lecture complète d'un titre, utilisée par les listes intelligentes
+pub fn record_play(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE files SET play_count = play_count + 1 WHERE path = ?1",
+        params![path],
+    )?;
+
+    Ok(())
+}
+
+// Chemins connus sous un dossier, qu'ils aient une empreinte ou seulement un titre indexé
+pub fn indexed_paths(conn: &Connection, dir: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT path FROM files WHERE substr(path, 1, length(?1)) = ?1
+         UNION
+         SELECT path FROM tracks WHERE substr(path, 1, length(?1)) = ?1",
+    )?;
+    let rows = stmt.query_map(params![dir], |row| row.get(0))?;
+
+    rows.collect()
+}
+
+pub fn remove_file(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    remove_track(conn, path)?;
+    conn.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+
+    Ok(())
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
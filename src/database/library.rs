@@ -0,0 +1,759 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use std::collections::HashMap;
+
+use super::rules::escape_like;
+use super::search::{index_album, index_artist, index_track};
+use crate::data::models::{
+    Album, Artist, ExternalIds, ExternalUrls, Image, Item, LibraryStats, ListingSort, MatchMethod,
+    Page, SortOrder, TrackFilter,
+};
+
+const ARTIST_COLUMNS: &str = "id, name, href, type, uri, spotify_url";
+
+const ALBUM_COLUMNS: &str =
+    "id, artist, album_type, total_tracks, available_markets, spotify_url, \
+     href, name, release_date, release_date_precision, type, uri";
+
+const TRACK_COLUMNS: &str = "id, album_id, artist, available_markets, disc_number, duration_ms, \
+     explicit, isrc, spotify_url, href, name, popularity, preview_url, track_number, type, uri, \
+     is_local, path, match_confidence, match_method, spotify_id";
+
+// Écriture
+
+pub fn save_track(conn: &Connection, track: &Item) -> rusqlite::Result<()> {
+    save_album(conn, &track.album)?;
+
+    for artist in &track.artists {
+        save_artist(conn, artist)?;
+    }
+
+    conn.execute(
+        &format!(
+            "INSERT INTO tracks ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)
+             ON CONFLICT(id) DO UPDATE SET
+                album_id = excluded.album_id,
+                artist = excluded.artist,
+                available_markets = excluded.available_markets,
+                disc_number = excluded.disc_number,
+                duration_ms = excluded.duration_ms,
+                explicit = excluded.explicit,
+                isrc = excluded.isrc,
+                spotify_url = excluded.spotify_url,
+                href = excluded.href,
+                name = excluded.name,
+                popularity = excluded.popularity,
+                preview_url = excluded.preview_url,
+                track_number = excluded.track_number,
+                type = excluded.type,
+                uri = excluded.uri,
+                is_local = excluded.is_local,
+                path = excluded.path,
+                match_confidence = excluded.match_confidence,
+                match_method = excluded.match_method,
+                spotify_id = excluded.spotify_id",
+            TRACK_COLUMNS
+        ),
+        params![
+            track.id,
+            track.album.id,
+            track.artist,
+            to_json(&track.available_markets),
+            track.disc_number,
+            track.duration_ms,
+            track.explicit,
+            track.external_ids.isrc,
+            track.external_urls.spotify,
+            track.href,
+            track.name,
+            track.popularity,
+            track.preview_url,
+            track.track_number,
+            track.type_field,
+            track.uri,
+            track.is_local,
+            track.path,
+            track.match_confidence,
+            track.match_method.map(|method| method.as_str()),
+            track.spotify_id,
+        ],
+    )?;
+
+    conn.execute(
+        "DELETE FROM track_artists WHERE track_id = ?1",
+        params![track.id],
+    )?;
+    for (position, artist) in track.artists.iter().enumerate() {
+        conn.execute(
+            "INSERT OR IGNORE INTO track_artists (track_id, artist_id, position) VALUES (?1, ?2, ?3)",
+            params![track.id, artist.id, position as i64],
+        )?;
+    }
+
+    index_track(conn, &track.id)?;
+    index_album(conn, &track.album.id)?;
+    for artist in track.artists.iter().chain(&track.album.artists) {
+        index_artist(conn, &artist.id)?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_track(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM tracks WHERE path = ?1", params![path])?;
+
+    Ok(())
+}
+
+// Supprime les albums, artistes et pochettes qui ne sont plus référencés par aucun titre
+pub fn prune_orphans(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM albums WHERE id NOT IN (SELECT album_id FROM tracks)",
+        [],
+    )?;
+    conn.execute(
+        "DELETE FROM artists
+         WHERE id NOT IN (SELECT artist_id FROM track_artists)
+         AND id NOT IN (SELECT artist_id FROM album_artists)",
+        [],
+    )?;
+    conn.execute(
+        "DELETE FROM covers
+         WHERE hash NOT IN (SELECT hash FROM album_covers)
+         AND hash NOT IN (SELECT hash FROM album_images WHERE hash IS NOT NULL)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn save_album(conn: &Connection, album: &Album) -> rusqlite::Result<()> {
+    for artist in &album.artists {
+        save_artist(conn, artist)?;
+    }
+
+    conn.execute(
+        &format!(
+            "INSERT INTO albums ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT(id) DO UPDATE SET
+                artist = excluded.artist,
+                album_type = excluded.album_type,
+                total_tracks = excluded.total_tracks,
+                available_markets = excluded.available_markets,
+                spotify_url = excluded.spotify_url,
+                href = excluded.href,
+                name = excluded.name,
+                release_date = excluded.release_date,
+                release_date_precision = excluded.release_date_precision,
+                type = excluded.type,
+                uri = excluded.uri",
+            ALBUM_COLUMNS
+        ),
+        params![
+            album.id,
+            album.artist,
+            album.album_type,
+            album.total_tracks,
+            to_json(&album.available_markets),
+            album.external_urls.spotify,
+            album.href,
+            album.name,
+            album.release_date,
+            album.release_date_precision,
+            album.type_field,
+            album.uri,
+        ],
+    )?;
+
+    // La copie locale d'une image est gardée tant que son URL ne change pas
+    conn.execute(
+        "DELETE FROM album_images WHERE album_id = ?1 AND position >= ?2",
+        params![album.id, album.images.len() as i64],
+    )?;
+    for (position, image) in album.images.iter().enumerate() {
+        let url = image.fallback_url.as_ref().unwrap_or(&image.url);
+        conn.execute(
+            "INSERT INTO album_images (album_id, position, url, height, width) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(album_id, position) DO UPDATE SET
+                hash = CASE WHEN url = excluded.url THEN hash END,
+                url = excluded.url,
+                height = excluded.height,
+                width = excluded.width",
+            params![album.id, position as i64, url, image.height, image.width],
+        )?;
+    }
+
+    // Les artistes déjà liés sont conservés, les nouveaux sont ajoutés à la suite
+    for artist in &album.artists {
+        conn.execute(
+            "INSERT OR IGNORE INTO album_artists (album_id, artist_id, position)
+             VALUES (?1, ?2, (SELECT COUNT(*) FROM album_artists WHERE album_id = ?1))",
+            params![album.id, artist.id],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn save_artist(conn: &Connection, artist: &Artist) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO artists ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                href = excluded.href,
+                type = excluded.type,
+                uri = excluded.uri,
+                spotify_url = excluded.spotify_url",
+            ARTIST_COLUMNS
+        ),
+        params![
+            artist.id,
+            artist.name,
+            artist.href,
+            artist.type_field,
+            artist.uri,
+            artist.external_urls.spotify,
+        ],
+    )?;
+
+    Ok(())
+}
+
+// Lecture
+
+// Titres filtrés, une page à la fois, avec le nombre total de résultats
+pub fn load_tracks(
+    conn: &Connection,
+    filter: &TrackFilter,
+    page: &Page,
+) -> rusqlite::Result<(Vec<Item>, usize)> {
+    let mut params = Vec::new();
+    let condition = filter_sql(filter, &mut params);
+    let total = count(
+        conn,
+        &format!("SELECT COUNT(*) FROM tracks WHERE {}", condition),
+        &params,
+    )?;
+
+    let mut albums = HashMap::new();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tracks
+         JOIN albums ON albums.id = tracks.album_id
+         LEFT JOIN files ON files.path = tracks.path
+         WHERE {} ORDER BY {}tracks.album_id, tracks.disc_number, tracks.track_number, tracks.id
+         LIMIT ? OFFSET ?",
+        prefixed(TRACK_COLUMNS, "tracks"),
+        condition,
+        order_sql(page, track_sort_sql)
+    ))?;
+    push_page(page, &mut params);
+    let rows = stmt.query_map(params_from_iter(params), track_from_row)?;
+
+    let mut tracks = Vec::new();
+    for row in rows {
+        let (mut track, album_id) = row?;
+        track.album = cached_album(conn, &mut albums, &album_id)?;
+        track.artists = load_track_artists(conn, &track.id)?;
+        tracks.push(track);
+    }
+
+    Ok((tracks, total))
+}
+
+pub fn load_track(conn: &Connection, id: &str) -> rusqlite::Result<Option<Item>> {
+    let row = conn
+        .query_row(
+            &format!("SELECT {} FROM tracks WHERE id = ?1", TRACK_COLUMNS),
+            params![id],
+            track_from_row,
+        )
+        .optional()?;
+
+    match row {
+        Some((mut track, album_id)) => {
+            track.album = load_album(conn, &album_id)?.unwrap_or_default();
+            track.artists = load_track_artists(conn, &track.id)?;
+            Ok(Some(track))
+        }
+        None => Ok(None),
+    }
+}
+
+// Titres sous un dossier, un chemin vide couvre toute la base ; la revue compte comme review_queue
+pub fn library_stats(conn: &Connection, dir: &str) -> rusqlite::Result<LibraryStats> {
+    let filter = TrackFilter {
+        path: dir.to_string(),
+        ..Default::default()
+    };
+    let mut params = Vec::new();
+    let condition = filter_sql(&filter, &mut params);
+
+    conn.query_row(
+        &format!(
+            "WITH selected AS (SELECT id, album_id, path, is_local FROM tracks WHERE {})
+             SELECT
+                (SELECT COUNT(*) FROM selected),
+                (SELECT COUNT(*) FROM selected WHERE is_local = 1),
+                (SELECT COUNT(DISTINCT album_id) FROM selected),
+                (SELECT COUNT(*) FROM (
+                    SELECT artist_id FROM track_artists WHERE track_id IN (SELECT id FROM selected)
+                    UNION
+                    SELECT artist_id FROM album_artists
+                    WHERE album_id IN (SELECT album_id FROM selected))),
+                (SELECT COUNT(*) FROM selected
+                 JOIN files ON files.path = selected.path
+                 LEFT JOIN match_decisions ON match_decisions.path = selected.path
+                 WHERE selected.is_local = 1 AND match_decisions.path IS NULL)",
+            condition
+        ),
+        params_from_iter(params),
+        |row| {
+            Ok(LibraryStats {
+                tracks: row.get::<_, i64>(0)? as usize,
+                local_tracks: row.get::<_, i64>(1)? as usize,
+                albums: row.get::<_, i64>(2)? as usize,
+                artists: row.get::<_, i64>(3)? as usize,
+                to_review: row.get::<_, i64>(4)? as usize,
+            })
+        },
+    )
+}
+
+pub fn track_id_for_path(conn: &Connection, path: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT id FROM tracks WHERE path = ?1",
+        params![path],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+// Albums ayant au moins un titre qui correspond au filtre, avec ces titres
+pub fn load_albums(
+    conn: &Connection,
+    filter: &TrackFilter,
+    page: &Page,
+) -> rusqlite::Result<(Vec<Album>, usize)> {
+    let mut params = Vec::new();
+    let condition = format!(
+        "EXISTS (SELECT 1 FROM tracks WHERE tracks.album_id = albums.id AND {})",
+        filter_sql(filter, &mut params)
+    );
+    let total = count(
+        conn,
+        &format!("SELECT COUNT(*) FROM albums WHERE {}", condition),
+        &params,
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT albums.id FROM albums WHERE {} ORDER BY {}albums.id LIMIT ? OFFSET ?",
+        condition,
+        order_sql(page, album_sort_sql)
+    ))?;
+    push_page(page, &mut params);
+    let ids = stmt
+        .query_map(params_from_iter(params), |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut albums = Vec::new();
+    for id in ids {
+        if let Some(mut album) = load_album(conn, &id)? {
+            let album_filter = TrackFilter {
+                album_id: Some(id),
+                ..filter.clone()
+            };
+            album.items = load_tracks(conn, &album_filter, &Page::default())?.0;
+            albums.push(album);
+        }
+    }
+
+    Ok((albums, total))
+}
+
+// Un artiste est rattaché aux albums sur lesquels il apparaît, en tant qu'artiste de l'album ou d'un titre
+const ARTIST_TRACKS: &str =
+    "(tracks.id IN (SELECT track_id FROM track_artists WHERE artist_id = artists.id)
+     OR tracks.album_id IN (SELECT album_id FROM album_artists WHERE artist_id = artists.id))";
+
+pub fn load_artists(
+    conn: &Connection,
+    filter: &TrackFilter,
+    page: &Page,
+) -> rusqlite::Result<(Vec<Artist>, usize)> {
+    let mut params = Vec::new();
+    let tracks_condition = filter_sql(filter, &mut params);
+    let condition = format!(
+        "EXISTS (SELECT 1 FROM tracks WHERE {} AND {})",
+        ARTIST_TRACKS, tracks_condition
+    );
+    let total = count(
+        conn,
+        &format!("SELECT COUNT(*) FROM artists WHERE {}", condition),
+        &params,
+    )?;
+    let album_params = params.clone();
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM artists WHERE {} ORDER BY {}artists.name COLLATE NOCASE, artists.id
+         LIMIT ? OFFSET ?",
+        ARTIST_COLUMNS,
+        condition,
+        order_sql(page, artist_sort_sql)
+    ))?;
+    push_page(page, &mut params);
+    let artists = stmt
+        .query_map(params_from_iter(params), artist_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut albums = HashMap::new();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT tracks.album_id FROM tracks, artists
+         WHERE artists.id = ? AND {} AND {} ORDER BY tracks.album_id",
+        ARTIST_TRACKS, tracks_condition
+    ))?;
+    let mut listed = Vec::new();
+    for mut artist in artists {
+        let mut params = vec![Value::Text(artist.id.clone())];
+        params.extend(album_params.iter().cloned());
+        for album_id in stmt.query_map(params_from_iter(params), |row| row.get::<_, String>(0))? {
+            artist
+                .albums
+                .push(cached_album(conn, &mut albums, &album_id?)?);
+        }
+        listed.push(artist);
+    }
+
+    Ok((listed, total))
+}
+
+// Conditions sur la table tracks, paramètres ajoutés dans l'ordre des `?`
+fn filter_sql(filter: &TrackFilter, params: &mut Vec<Value>) -> String {
+    let mut conditions = vec![within_sql(std::slice::from_ref(&filter.path), params)];
+
+    if let Some(artist_id) = &filter.artist_id {
+        conditions.push(
+            "(tracks.id IN (SELECT track_id FROM track_artists WHERE artist_id = ?)
+              OR tracks.album_id IN (SELECT album_id FROM album_artists WHERE artist_id = ?))"
+                .to_string(),
+        );
+        params.push(Value::Text(artist_id.clone()));
+        params.push(Value::Text(artist_id.clone()));
+    }
+    if let Some(album_id) = &filter.album_id {
+        conditions.push("tracks.album_id = ?".to_string());
+        params.push(Value::Text(album_id.clone()));
+    }
+    if let Some(format) = &filter.format {
+        let extension = format.trim_start_matches('.').to_lowercase();
+        conditions.push("lower(tracks.path) LIKE ? ESCAPE '\\'".to_string());
+        params.push(Value::Text(format!("%.{}", escape_like(&extension))));
+    }
+    if let Some(explicit) = filter.explicit {
+        conditions.push("tracks.explicit = ?".to_string());
+        params.push(Value::Integer(explicit as i64));
+    }
+
+    conditions.join(" AND ")
+}
+
+// Titres rangés sous un des préfixes donnés, aucun si la liste est vide
+pub fn within_sql(prefixes: &[String], params: &mut Vec<Value>) -> String {
+    if prefixes.is_empty() {
+        return "0".to_string();
+    }
+
+    let conditions: Vec<&str> = prefixes
+        .iter()
+        .map(|prefix| {
+            params.push(Value::Text(prefix.clone()));
+            params.push(Value::Text(prefix.clone()));
+            "substr(tracks.path, 1, length(?)) = ?"
+        })
+        .collect();
+
+    format!("({})", conditions.join(" OR "))
+}
+
+fn track_sort_sql(sort: ListingSort) -> String {
+    match sort {
+        ListingSort::Name => "tracks.name COLLATE NOCASE".to_string(),
+        ListingSort::Artist => "COALESCE((SELECT artists.name FROM track_artists
+             JOIN artists ON artists.id = track_artists.artist_id
+             WHERE track_artists.track_id = tracks.id ORDER BY track_artists.position LIMIT 1),
+             tracks.artist) COLLATE NOCASE"
+            .to_string(),
+        ListingSort::Added => "files.added_at".to_string(),
+        ListingSort::ReleaseDate => "albums.release_date".to_string(),
+        ListingSort::Duration => "tracks.duration_ms".to_string(),
+    }
+}
+
+fn album_sort_sql(sort: ListingSort) -> String {
+    match sort {
+        ListingSort::Name => "albums.name COLLATE NOCASE".to_string(),
+        ListingSort::Artist => "COALESCE((SELECT artists.name FROM album_artists
+             JOIN artists ON artists.id = album_artists.artist_id
+             WHERE album_artists.album_id = albums.id ORDER BY album_artists.position LIMIT 1),
+             albums.artist) COLLATE NOCASE"
+            .to_string(),
+        ListingSort::Added => "(SELECT MAX(files.added_at) FROM tracks
+             JOIN files ON files.path = tracks.path WHERE tracks.album_id = albums.id)"
+            .to_string(),
+        ListingSort::ReleaseDate => "albums.release_date".to_string(),
+        ListingSort::Duration => {
+            "(SELECT SUM(tracks.duration_ms) FROM tracks WHERE tracks.album_id = albums.id)"
+                .to_string()
+        }
+    }
+}
+
+// Pour un artiste, date d'ajout et de sortie les plus récentes, durée totale
+fn artist_sort_sql(sort: ListingSort) -> String {
+    match sort {
+        ListingSort::Name | ListingSort::Artist => "artists.name COLLATE NOCASE".to_string(),
+        ListingSort::Added => format!(
+            "(SELECT MAX(files.added_at) FROM tracks JOIN files ON files.path = tracks.path
+              WHERE {})",
+            ARTIST_TRACKS
+        ),
+        ListingSort::ReleaseDate => format!(
+            "(SELECT MAX(albums.release_date) FROM tracks JOIN albums ON albums.id = tracks.album_id
+              WHERE {})",
+            ARTIST_TRACKS
+        ),
+        ListingSort::Duration => format!(
+            "(SELECT SUM(tracks.duration_ms) FROM tracks WHERE {})",
+            ARTIST_TRACKS
+        ),
+    }
+}
+
+// Tri demandé suivi d'une virgule, l'ordre par défaut départage les égalités
+fn order_sql(page: &Page, sort_sql: fn(ListingSort) -> String) -> String {
+    match page.sort {
+        Some(sort) => {
+            let direction = match page.order {
+                SortOrder::Asc => "ASC",
+                SortOrder::Desc => "DESC",
+            };
+            format!("{} {}, ", sort_sql(sort), direction)
+        }
+        None => String::new(),
+    }
+}
+
+fn push_page(page: &Page, params: &mut Vec<Value>) {
+    params.push(Value::Integer(page.limit.map_or(-1, |limit| limit as i64)));
+    params.push(Value::Integer(page.offset as i64));
+}
+
+fn count(conn: &Connection, sql: &str, params: &[Value]) -> rusqlite::Result<usize> {
+    conn.query_row(sql, params_from_iter(params), |row| row.get::<_, i64>(0))
+        .map(|total| total as usize)
+}
+
+fn cached_album(
+    conn: &Connection,
+    albums: &mut HashMap<String, Album>,
+    id: &str,
+) -> rusqlite::Result<Album> {
+    if let Some(album) = albums.get(id) {
+        return Ok(album.clone());
+    }
+
+    let album = load_album(conn, id)?.unwrap_or_default();
+    albums.insert(id.to_string(), album.clone());
+    Ok(album)
+}
+
+pub fn load_album(conn: &Connection, id: &str) -> rusqlite::Result<Option<Album>> {
+    let album = conn
+        .query_row(
+            &format!("SELECT {} FROM albums WHERE id = ?1", ALBUM_COLUMNS),
+            params![id],
+            album_from_row,
+        )
+        .optional()?;
+
+    match album {
+        Some(mut album) => {
+            album.images = load_album_images(conn, &album.id)?;
+            album.artists = load_album_artists(conn, &album.id)?;
+            Ok(Some(album))
+        }
+        None => Ok(None),
+    }
+}
+
+// Albums d'un artiste ayant un titre sous un des préfixes, du plus récent au plus ancien, sans leurs titres
+pub fn load_artist_albums(
+    conn: &Connection,
+    id: &str,
+    prefixes: &[String],
+) -> rusqlite::Result<Vec<Album>> {
+    let mut params = vec![Value::Text(id.to_string())];
+    let within = within_sql(prefixes, &mut params);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM albums
+         WHERE (id IN (SELECT album_id FROM tracks
+                       WHERE id IN (SELECT track_id FROM track_artists WHERE artist_id = ?1))
+                OR id IN (SELECT album_id FROM album_artists WHERE artist_id = ?1))
+         AND EXISTS (SELECT 1 FROM tracks WHERE tracks.album_id = albums.id AND {})
+         ORDER BY release_date DESC, name COLLATE NOCASE, id",
+        within
+    ))?;
+    let ids = stmt
+        .query_map(params_from_iter(params), |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut albums = Vec::new();
+    for id in ids {
+        albums.extend(load_album(conn, &id)?);
+    }
+
+    Ok(albums)
+}
+
+pub fn load_artist(conn: &Connection, id: &str) -> rusqlite::Result<Option<Artist>> {
+    conn.query_row(
+        &format!("SELECT {} FROM artists WHERE id = ?1", ARTIST_COLUMNS),
+        params![id],
+        artist_from_row,
+    )
+    .optional()
+}
+
+fn load_album_images(conn: &Connection, album_id: &str) -> rusqlite::Result<Vec<Image>> {
+    let mut stmt = conn.prepare(
+        "SELECT url, height, width, hash, position FROM album_images
+         WHERE album_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map(params![album_id], |row| {
+        let url: String = row.get(0)?;
+        let cached = row.get::<_, Option<String>>(3)?.is_some();
+        let position: i64 = row.get(4)?;
+
+        // Image déjà téléchargée : URL locale, celle de Spotify en secours
+        Ok(Image {
+            url: if cached {
+                format!("/albums/{}/images/{}", album_id, position)
+            } else {
+                url.clone()
+            },
+            height: row.get(1)?,
+            width: row.get(2)?,
+            fallback_url: cached.then_some(url),
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn load_album_artists(conn: &Connection, album_id: &str) -> rusqlite::Result<Vec<Artist>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM artists JOIN album_artists ON album_artists.artist_id = artists.id
+         WHERE album_artists.album_id = ?1 ORDER BY album_artists.position",
+        prefixed(ARTIST_COLUMNS, "artists")
+    ))?;
+    let rows = stmt.query_map(params![album_id], artist_from_row)?;
+
+    rows.collect()
+}
+
+fn load_track_artists(conn: &Connection, track_id: &str) -> rusqlite::Result<Vec<Artist>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM artists JOIN track_artists ON track_artists.artist_id = artists.id
+         WHERE track_artists.track_id = ?1 ORDER BY track_artists.position",
+        prefixed(ARTIST_COLUMNS, "artists")
+    ))?;
+    let rows = stmt.query_map(params![track_id], artist_from_row)?;
+
+    rows.collect()
+}
+
+fn artist_from_row(row: &Row) -> rusqlite::Result<Artist> {
+    Ok(Artist {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        href: row.get(2)?,
+        type_field: row.get(3)?,
+        uri: row.get(4)?,
+        external_urls: ExternalUrls {
+            spotify: row.get(5)?,
+        },
+        albums: Vec::new(),
+    })
+}
+
+fn album_from_row(row: &Row) -> rusqlite::Result<Album> {
+    Ok(Album {
+        id: row.get(0)?,
+        artist: row.get(1)?,
+        album_type: row.get(2)?,
+        total_tracks: row.get(3)?,
+        available_markets: from_json(row.get(4)?),
+        external_urls: ExternalUrls {
+            spotify: row.get(5)?,
+        },
+        href: row.get(6)?,
+        name: row.get(7)?,
+        release_date: row.get(8)?,
+        release_date_precision: row.get(9)?,
+        type_field: row.get(10)?,
+        uri: row.get(11)?,
+        images: Vec::new(),
+        artists: Vec::new(),
+        items: Vec::new(),
+    })
+}
+
+fn track_from_row(row: &Row) -> rusqlite::Result<(Item, String)> {
+    let track = Item {
+        id: row.get(0)?,
+        artist: row.get(2)?,
+        available_markets: from_json(row.get(3)?),
+        disc_number: row.get(4)?,
+        duration_ms: row.get(5)?,
+        explicit: row.get(6)?,
+        external_ids: ExternalIds { isrc: row.get(7)? },
+        external_urls: ExternalUrls {
+            spotify: row.get(8)?,
+        },
+        href: row.get(9)?,
+        name: row.get(10)?,
+        popularity: row.get(11)?,
+        preview_url: row.get(12)?,
+        track_number: row.get(13)?,
+        type_field: row.get(14)?,
+        uri: row.get(15)?,
+        is_local: row.get(16)?,
+        path: row.get(17)?,
+        library: None,
+        match_confidence: row.get(18)?,
+        match_method: row
+            .get::<_, Option<String>>(19)?
+            .and_then(|method| MatchMethod::from_name(&method)),
+        spotify_id: row.get(20)?,
+        album: Album::default(),
+        artists: Vec::new(),
+    };
+
+    Ok((track, row.get(1)?))
+}
+
+fn prefixed(columns: &str, table: &str) -> String {
+    columns
+        .split(", ")
+        .map(|column| format!("{}.{}", table, column.trim()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn to_json(values: &[String]) -> String {
+    serde_json::to_string(values).unwrap_or_else(|_| "[]".to_string())
+}
+
+fn from_json(value: String) -> Vec<String> {
+    serde_json::from_str(&value).unwrap_or_default()
+}
@@ -0,0 +1,124 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use super::files::now;
+use super::library::within_sql;
+use crate::data::models::{Item, MatchCandidate, MatchDecision, Page};
+
+// Remplace les candidats Spotify retenus pour la revue d'un fichier
+pub fn save_candidates(
+    conn: &Connection,
+    path: &str,
+    candidates: &[MatchCandidate],
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM match_candidates WHERE path = ?1",
+        params![path],
+    )?;
+
+    for (position, candidate) in candidates.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO match_candidates (path, position, spotify_id, score, track)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                path,
+                position as i64,
+                candidate.track.id,
+                candidate.score,
+                serde_json::to_string(&candidate.track).unwrap_or_default(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn load_candidates(conn: &Connection, path: &str) -> rusqlite::Result<Vec<MatchCandidate>> {
+    let mut stmt = conn
+        .prepare("SELECT score, track FROM match_candidates WHERE path = ?1 ORDER BY position")?;
+    let rows = stmt.query_map(params![path], |row| {
+        Ok((row.get::<_, f64>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut candidates = Vec::new();
+    for row in rows {
+        let (score, track) = row?;
+        if let Ok(track) = serde_json::from_str::<Item>(&track) {
+            candidates.push(MatchCandidate { score, track });
+        }
+    }
+
+    Ok(candidates)
+}
+
+// Page des titres locaux sans décision sous un des préfixes : sans correspondance ou avec des
+// candidats incertains, et nombre total
+pub fn review_queue(
+    conn: &Connection,
+    prefixes: &[String],
+    page: &Page,
+) -> rusqlite::Result<(Vec<String>, usize)> {
+    let mut params = Vec::new();
+    let condition = format!(
+        "tracks.is_local = 1 AND match_decisions.path IS NULL AND {}",
+        within_sql(prefixes, &mut params)
+    );
+    let from = "FROM tracks
+         JOIN files ON files.path = tracks.path
+         LEFT JOIN match_decisions ON match_decisions.path = tracks.path";
+    let total: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) {} WHERE {}", from, condition),
+        params_from_iter(&params),
+        |row| row.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT tracks.id {} WHERE {} ORDER BY tracks.path LIMIT ? OFFSET ?",
+        from, condition
+    ))?;
+    params.push(Value::Integer(page.limit.map_or(-1, |limit| limit as i64)));
+    params.push(Value::Integer(page.offset as i64));
+    let rows = stmt.query_map(params_from_iter(params), |row| row.get(0))?;
+
+    Ok((rows.collect::<rusqlite::Result<_>>()?, total as usize))
+}
+
+pub fn load_decision(conn: &Connection, path: &str) -> rusqlite::Result<Option<MatchDecision>> {
+    let row = conn
+        .query_row(
+            "SELECT decision, track FROM match_decisions WHERE path = ?1",
+            params![path],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .optional()?;
+
+    Ok(match row {
+        Some((decision, Some(track))) if decision == "spotify" => serde_json::from_str(&track)
+            .ok()
+            .map(|track| MatchDecision::Spotify(Box::new(track))),
+        Some(_) => Some(MatchDecision::Local),
+        None => None,
+    })
+}
+
+pub fn save_decision(
+    conn: &Connection,
+    path: &str,
+    decision: &MatchDecision,
+) -> rusqlite::Result<()> {
+    let (kind, track) = match decision {
+        MatchDecision::Spotify(track) => ("spotify", serde_json::to_string(track).ok()),
+        MatchDecision::Local => ("local", None),
+    };
+
+    conn.execute(
+        "INSERT INTO match_decisions (path, decision, track, decided_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(path) DO UPDATE SET
+            decision = excluded.decision,
+            track = excluded.track,
+            decided_at = excluded.decided_at",
+        params![path, kind, track, now()],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+
+use super::files::now;
+use super::library::{load_track, within_sql};
+use super::rules::smart_track_ids;
+use crate::data::models::Playlist;
+use uuid::Uuid;
+
+const PLAYLIST_COLUMNS: &str =
+    "id, name, description, cover, source, created_at, updated_at, rules";
+
+pub fn create_playlist(conn: &Connection, playlist: &Playlist) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO playlists ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            PLAYLIST_COLUMNS
+        ),
+        params![
+            playlist.id,
+            playlist.name,
+            playlist.description,
+            playlist.cover,
+            playlist.source,
+            playlist.created_at,
+            playlist.updated_at,
+            rules_json(playlist),
+        ],
+    )?;
+
+    save_entries(conn, &playlist.id, &playlist.track_ids)
+}
+
+pub fn update_playlist(conn: &Connection, playlist: &Playlist) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE playlists SET name = ?2, description = ?3, cover = ?4, rules = ?5, updated_at = ?6,
+         edited = 1 WHERE id = ?1",
+        params![
+            playlist.id,
+            playlist.name,
+            playlist.description,
+            playlist.cover,
+            rules_json(playlist),
+            now(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_playlist(conn: &Connection, id: &str) -> rusqlite::Result<bool> {
+    let deleted = conn.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+
+    Ok(deleted > 0)
+}
+
+// L'ordre est réécrit en entier : les listes restent petites et les positions contiguës
+pub fn save_entries(conn: &Connection, id: &str, track_ids: &[String]) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM playlist_entries WHERE playlist_id = ?1",
+        params![id],
+    )?;
+    for (position, track_id) in track_ids.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO playlist_entries (playlist_id, position, track_id) VALUES (?1, ?2, ?3)",
+            params![id, position as i64, track_id],
+        )?;
+    }
+    conn.execute(
+        "UPDATE playlists SET updated_at = ?2 WHERE id = ?1",
+        params![id, now()],
+    )?;
+
+    Ok(())
+}
+
+// Une liste importée modifiée depuis l'API n'est plus réécrite par son fichier
+pub fn mark_edited(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.execute("UPDATE playlists SET edited = 1 WHERE id = ?1", params![id])?;
+
+    Ok(())
+}
+
+// Les titres des listes sont limités aux préfixes donnés
+pub fn load_playlists(conn: &Connection, prefixes: &[String]) -> rusqlite::Result<Vec<Playlist>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM playlists ORDER BY name COLLATE NOCASE",
+        PLAYLIST_COLUMNS
+    ))?;
+    let rows = stmt.query_map([], playlist_from_row)?;
+
+    let mut playlists = Vec::new();
+    for row in rows {
+        let mut playlist = row?;
+        playlist.track_ids = playlist_track_ids(conn, &playlist, prefixes)?;
+        playlists.push(playlist);
+    }
+
+    Ok(playlists)
+}
+
+// Liste avec ses titres complets ; ceux qui ne sont plus indexés ou hors des préfixes sont ignorés
+pub fn load_playlist(
+    conn: &Connection,
+    id: &str,
+    prefixes: &[String],
+) -> rusqlite::Result<Option<Playlist>> {
+    let playlist = conn
+        .query_row(
+            &format!("SELECT {} FROM playlists WHERE id = ?1", PLAYLIST_COLUMNS),
+            params![id],
+            playlist_from_row,
+        )
+        .optional()?;
+
+    let Some(mut playlist) = playlist else {
+        return Ok(None);
+    };
+
+    playlist.track_ids = playlist_track_ids(conn, &playlist, prefixes)?;
+    for track_id in &playlist.track_ids {
+        if let Some(track) = load_track(conn, track_id)? {
+            playlist.tracks.push(track);
+        }
+    }
+
+    Ok(Some(playlist))
+}
+
+// Positions et titres des entrées indexées sous un des préfixes, dans l'ordre de la liste
+pub fn visible_entries(
+    conn: &Connection,
+    id: &str,
+    prefixes: &[String],
+) -> rusqlite::Result<Vec<(usize, String)>> {
+    let mut params = vec![Value::Text(id.to_string())];
+    let within = within_sql(prefixes, &mut params);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT playlist_entries.position, playlist_entries.track_id FROM playlist_entries
+         JOIN tracks ON tracks.id = playlist_entries.track_id
+         WHERE playlist_entries.playlist_id = ? AND {}
+         ORDER BY playlist_entries.position",
+        within
+    ))?;
+    let rows = stmt.query_map(params_from_iter(params), |row| {
+        Ok((row.get::<_, i64>(0)? as usize, row.get(1)?))
+    })?;
+
+    rows.collect()
+}
+
+pub fn load_entries(conn: &Connection, id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT track_id FROM playlist_entries WHERE playlist_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map(params![id], |row| row.get(0))?;
+
+    rows.collect()
+}
+
+// Titres enregistrés, ou ceux qui satisfont les règles d'une liste intelligente
+fn playlist_track_ids(
+    conn: &Connection,
+    playlist: &Playlist,
+    prefixes: &[String],
+) -> rusqlite::Result<Vec<String>> {
+    match &playlist.rules {
+        Some(rules) => smart_track_ids(conn, rules, prefixes),
+        None => Ok(visible_entries(conn, &playlist.id, prefixes)?
+            .into_iter()
+            .map(|(_, track_id)| track_id)
+            .collect()),
+    }
+}
+
+fn playlist_from_row(row: &Row) -> rusqlite::Result<Playlist> {
+    let rules: Option<String> = row.get(7)?;
+
+    Ok(Playlist {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        cover: row.get(3)?,
+        source: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        rules: rules.and_then(|rules| serde_json::from_str(&rules).ok()),
+        track_ids: Vec::new(),
+        tracks: Vec::new(),
+    })
+}
+
+fn rules_json(playlist: &Playlist) -> Option<String> {
+    playlist
+        .rules
+        .as_ref()
+        .and_then(|rules| serde_json::to_string(rules).ok())
+}
+
+// None si la liste n'existe pas, sinon indique si elle est définie par des règles
+pub fn is_smart_playlist(conn: &Connection, id: &str) -> rusqlite::Result<Option<bool>> {
+    conn.query_row(
+        "SELECT rules IS NOT NULL FROM playlists WHERE id = ?1",
+        params![id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn find_imported_playlist(
+    conn: &Connection,
+    source: &str,
+) -> rusqlite::Result<Option<(String, bool)>> {
+    conn.query_row(
+        "SELECT id, edited FROM playlists WHERE source = ?1",
+        params![source],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+// Listes importées depuis des fichiers du dossier, hors celles modifiées depuis l'API
+pub fn imported_playlists(conn: &Connection, dir: &str) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, source FROM playlists
+         WHERE source IS NOT NULL AND NOT edited AND substr(source, 1, length(?1)) = ?1",
+    )?;
+    let rows = stmt.query_map(params![dir], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+// Crée ou met à jour la liste liée à un fichier ; l'ordre n'est réécrit que s'il a changé,
+// et jamais si la liste a été modifiée depuis l'API
+pub fn save_imported_playlist(
+    conn: &Connection,
+    source: &str,
+    name: &str,
+    track_ids: &[String],
+) -> rusqlite::Result<String> {
+    let Some((id, edited)) = find_imported_playlist(conn, source)? else {
+        let created_at = now();
+        let playlist = Playlist {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            cover: None,
+            source: Some(source.to_string()),
+            created_at,
+            updated_at: created_at,
+            rules: None,
+            track_ids: track_ids.to_vec(),
+            tracks: Vec::new(),
+        };
+        create_playlist(conn, &playlist)?;
+        return Ok(playlist.id);
+    };
+    if edited {
+        return Ok(id);
+    }
+
+    conn.execute(
+        "UPDATE playlists SET name = ?2 WHERE id = ?1",
+        params![id, name],
+    )?;
+    if load_entries(conn, &id)? != track_ids {
+        save_entries(conn, &id, track_ids)?;
+    }
+
+    Ok(id)
+}
@@ -0,0 +1,411 @@
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+
+use super::library::within_sql;
+use crate::data::models::{
+    Combinator, Condition, DateRule, FlagRule, FormatRule, NumberRule, Rule, SmartRules, SortField,
+    SortOrder, TextRule,
+};
+
+// Titres d'une liste intelligente rangés sous un des préfixes, évalués sur l'index à chaque lecture
+pub fn smart_track_ids(
+    conn: &Connection,
+    rules: &SmartRules,
+    prefixes: &[String],
+) -> rusqlite::Result<Vec<String>> {
+    let mut params = Vec::new();
+    let within = within_sql(prefixes, &mut params);
+    let filter = group_sql(rules.combinator, &rules.rules, &mut params);
+    let order = match &rules.sort {
+        Some(sort) => format!("{} {}", sort_sql(sort.field), order_sql(sort.order)),
+        None => "tracks.artist COLLATE NOCASE, albums.name COLLATE NOCASE, \
+                 tracks.disc_number, tracks.track_number"
+            .to_string(),
+    };
+    params.push(Value::Integer(rules.limit.map_or(-1, i64::from)));
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT tracks.id FROM tracks
+         JOIN albums ON albums.id = tracks.album_id
+         LEFT JOIN files ON files.path = tracks.path
+         WHERE {} AND {} ORDER BY {}, tracks.id LIMIT ?",
+        within, filter, order
+    ))?;
+    let rows = stmt.query_map(params_from_iter(params), |row| row.get(0))?;
+
+    rows.collect()
+}
+
+// Vérifie les valeurs que la désérialisation ne contrôle pas
+pub fn check_rules(rules: &SmartRules) -> Result<(), String> {
+    check_group(&rules.rules)
+}
+
+fn check_group(rules: &[Rule]) -> Result<(), String> {
+    for rule in rules {
+        match rule {
+            Rule::Group { rules, .. } => check_group(rules)?,
+            Rule::Condition(Condition::ReleaseDate(rule) | Condition::Added(rule)) => match rule {
+                DateRule::Is(date) | DateRule::Before(date) | DateRule::After(date)
+                    if !is_date(date) =>
+                {
+                    return Err(format!(
+                        "Invalid date {}, expected YYYY, YYYY-MM or YYYY-MM-DD",
+                        date
+                    ));
+                }
+                _ => {}
+            },
+            Rule::Condition(Condition::Format(
+                FormatRule::Is(format) | FormatRule::IsNot(format),
+            )) if format.trim_start_matches('.').is_empty() => {
+                return Err("Format cannot be empty".to_string());
+            }
+            Rule::Condition(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let lengths: &[usize] = match parts.len() {
+        1 => &[4],
+        2 => &[4, 2],
+        3 => &[4, 2, 2],
+        _ => return false,
+    };
+
+    parts
+        .iter()
+        .zip(lengths)
+        .all(|(part, length)| part.len() == *length && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn group_sql(combinator: Combinator, rules: &[Rule], params: &mut Vec<Value>) -> String {
+    let (separator, empty) = match combinator {
+        Combinator::All => (" AND ", "1"),
+        Combinator::Any => (" OR ", "0"),
+    };
+    if rules.is_empty() {
+        return empty.to_string();
+    }
+
+    let conditions: Vec<String> = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Group { combinator, rules } => group_sql(*combinator, rules, params),
+            Rule::Condition(condition) => condition_sql(condition, params),
+        })
+        .collect();
+
+    format!("({})", conditions.join(separator))
+}
+
+fn condition_sql(condition: &Condition, params: &mut Vec<Value>) -> String {
+    match condition {
+        Condition::Artist(rule) => {
+            // Artiste principal du fichier ou un des artistes crédités du titre
+            let track = text_sql("tracks.artist", rule, params);
+            let credited = text_sql("artists.name", rule, params);
+            let any = format!(
+                "({} OR EXISTS (SELECT 1 FROM track_artists
+                    JOIN artists ON artists.id = track_artists.artist_id
+                    WHERE track_artists.track_id = tracks.id AND {}))",
+                track, credited
+            );
+            negate_text(rule, any)
+        }
+        Condition::Album(rule) => negate_text(rule, text_sql("albums.name", rule, params)),
+        Condition::ReleaseDate(rule) => date_sql("albums.release_date", rule, params),
+        Condition::Added(rule) => date_sql("date(files.added_at, 'unixepoch')", rule, params),
+        Condition::DurationMs(rule) => number_sql("tracks.duration_ms", rule, params),
+        Condition::Popularity(rule) => number_sql("tracks.popularity", rule, params),
+        Condition::PlayCount(rule) => number_sql("COALESCE(files.play_count, 0)", rule, params),
+        Condition::Explicit(FlagRule::Is(explicit)) => {
+            params.push(Value::Integer(*explicit as i64));
+            "tracks.explicit = ?".to_string()
+        }
+        Condition::Format(rule) => {
+            let (format, negated) = match rule {
+                FormatRule::Is(format) => (format, false),
+                FormatRule::IsNot(format) => (format, true),
+            };
+            let extension = format.trim_start_matches('.').to_lowercase();
+            params.push(Value::Text(format!("%.{}", escape_like(&extension))));
+            negate(negated, "lower(tracks.path) LIKE ? ESCAPE '\\'".to_string())
+        }
+    }
+}
+
+// Comparaison positive, la négation est appliquée à l'ensemble par negate_text
+fn text_sql(column: &str, rule: &TextRule, params: &mut Vec<Value>) -> String {
+    match rule {
+        TextRule::Is(value) | TextRule::IsNot(value) => {
+            params.push(Value::Text(value.clone()));
+            format!("{} = ? COLLATE NOCASE", column)
+        }
+        TextRule::Contains(value) | TextRule::NotContains(value) => {
+            params.push(Value::Text(format!("%{}%", escape_like(value))));
+            format!("{} LIKE ? ESCAPE '\\'", column)
+        }
+        TextRule::StartsWith(value) => {
+            params.push(Value::Text(format!("{}%", escape_like(value))));
+            format!("{} LIKE ? ESCAPE '\\'", column)
+        }
+    }
+}
+
+fn negate_text(rule: &TextRule, sql: String) -> String {
+    negate(
+        matches!(rule, TextRule::IsNot(_) | TextRule::NotContains(_)),
+        sql,
+    )
+}
+
+fn negate(negated: bool, sql: String) -> String {
+    if negated {
+        format!("NOT ({})", sql)
+    } else {
+        sql
+    }
+}
+
+fn number_sql(column: &str, rule: &NumberRule, params: &mut Vec<Value>) -> String {
+    let (operator, value) = match rule {
+        NumberRule::Is(value) => ("=", value),
+        NumberRule::IsNot(value) => ("<>", value),
+        NumberRule::Gt(value) => (">", value),
+        NumberRule::Lt(value) => ("<", value),
+    };
+    params.push(Value::Integer(*value));
+
+    format!("{} {} ?", column, operator)
+}
+
+// Dates comparées sous forme de texte AAAA-MM-JJ, une date partielle couvre toute sa période :
+// une année seule va du 01-01 au 12-31, un mois du 01 au 31
+fn date_sql(column: &str, rule: &DateRule, params: &mut Vec<Value>) -> String {
+    let within = |date: &str, params: &mut Vec<Value>| {
+        params.push(Value::Text(date.to_string()));
+        params.push(Value::Text(format!("{}-%", date)));
+        format!("({} = ? OR {} LIKE ?)", column, column)
+    };
+
+    match rule {
+        DateRule::Is(date) => within(date, params),
+        DateRule::Before(date) => {
+            params.push(Value::Text(period_start(date)));
+            format!("{} < ?", period_sql(column, "12-31", "31"))
+        }
+        DateRule::After(date) => {
+            params.push(Value::Text(period_end(date)));
+            format!("{} > ?", period_sql(column, "01-01", "01"))
+        }
+        DateRule::InLast(days) => {
+            params.push(Value::Text(format!("-{} days", days)));
+            format!("{} >= date('now', ?)", period_sql(column, "12-31", "31"))
+        }
+        DateRule::NotInLast(days) => {
+            params.push(Value::Text(format!("-{} days", days)));
+            format!("{} < date('now', ?)", period_sql(column, "12-31", "31"))
+        }
+    }
+}
+
+// Date stockée complétée au début ou à la fin de sa période
+fn period_sql(column: &str, year: &str, month: &str) -> String {
+    format!(
+        "(CASE length({0}) WHEN 4 THEN {0} || '-{1}' WHEN 7 THEN {0} || '-{2}' ELSE {0} END)",
+        column, year, month
+    )
+}
+
+fn period_start(date: &str) -> String {
+    match date.len() {
+        4 => format!("{}-01-01", date),
+        7 => format!("{}-01", date),
+        _ => date.to_string(),
+    }
+}
+
+fn period_end(date: &str) -> String {
+    match date.len() {
+        4 => format!("{}-12-31", date),
+        7 => format!("{}-31", date),
+        _ => date.to_string(),
+    }
+}
+
+fn sort_sql(field: SortField) -> &'static str {
+    match field {
+        SortField::Name => "tracks.name COLLATE NOCASE",
+        SortField::Artist => "tracks.artist COLLATE NOCASE",
+        SortField::Album => "albums.name COLLATE NOCASE",
+        SortField::ReleaseDate => "albums.release_date",
+        SortField::DurationMs => "tracks.duration_ms",
+        SortField::Popularity => "tracks.popularity",
+        SortField::PlayCount => "COALESCE(files.play_count, 0)",
+        SortField::Added => "files.added_at",
+        SortField::Random => "random()",
+    }
+}
+
+fn order_sql(order: SortOrder) -> &'static str {
+    match order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    }
+}
+
+pub fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::models::{Album, Artist, FileFingerprint, Item};
+    use crate::database::database::Database;
+    use crate::database::files::{record_play, save_fingerprint};
+    use crate::database::library::save_track;
+    use serde_json::json;
+
+    #[test]
+    fn rules_filter_sort_and_limit_tracks() {
+        let db = Database::open(":memory:").unwrap();
+        let conn = db.connection();
+
+        let tracks = [
+            ("a", "Daft Punk", "flac", 80, "2001-03-12"),
+            ("b", "Daft Punk", "mp3", 90, "2001-03-12"),
+            ("c", "Air", "flac", 60, "1998"),
+            ("d", "Justice", "FLAC", 40, "2007-06-11"),
+        ];
+        for (id, artist, format, popularity, release_date) in tracks {
+            let path = format!("/music/{}.{}", id, format);
+            let track = Item {
+                id: id.to_string(),
+                name: id.to_uppercase(),
+                path: path.clone(),
+                popularity,
+                artists: vec![Artist {
+                    id: artist.to_lowercase(),
+                    name: artist.to_string(),
+                    ..Default::default()
+                }],
+                album: Album {
+                    id: format!("album-{}", release_date),
+                    name: "Album".to_string(),
+                    release_date: release_date.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&conn, &track).unwrap();
+            save_fingerprint(
+                &conn,
+                &FileFingerprint {
+                    path,
+                    size: 0,
+                    mtime: 0,
+                    hash: String::new(),
+                },
+            )
+            .unwrap();
+        }
+        record_play(&conn, "/music/c.flac").unwrap();
+        record_play(&conn, "/music/c.flac").unwrap();
+
+        let music = ["/music/".to_string()];
+        let rules = |value: serde_json::Value| -> SmartRules {
+            let rules = serde_json::from_value(value).unwrap();
+            check_rules(&rules).unwrap();
+            rules
+        };
+
+        // FLAC ajoutés ces 30 derniers jours, populaires ou de Justice
+        let flac = rules(json!({
+            "rules": [
+                { "field": "format", "op": "is", "value": "flac" },
+                { "field": "added", "op": "in_last", "value": 30 },
+                { "match": "any", "rules": [
+                    { "field": "popularity", "op": "gt", "value": 50 },
+                    { "field": "artist", "op": "is", "value": "justice" },
+                ] },
+            ],
+            "sort": { "field": "popularity", "order": "desc" },
+        }));
+        assert_eq!(
+            smart_track_ids(&conn, &flac, &music).unwrap(),
+            ["a", "c", "d"]
+        );
+
+        let limited = rules(json!({
+            "match": "any",
+            "rules": [
+                { "field": "release_date", "op": "is", "value": "2001" },
+                { "field": "play_count", "op": "gt", "value": 1 },
+            ],
+            "sort": { "field": "name" },
+            "limit": 2,
+        }));
+        assert_eq!(
+            smart_track_ids(&conn, &limited, &music).unwrap(),
+            ["a", "b"]
+        );
+
+        let after = rules(json!({
+            "rules": [
+                { "field": "release_date", "op": "after", "value": "2001" },
+                { "field": "artist", "op": "not_contains", "value": "punk" },
+            ],
+        }));
+        assert_eq!(smart_track_ids(&conn, &after, &music).unwrap(), ["d"]);
+        // Année seule : l'album couvre toute l'année, y compris les derniers jours
+        let year: String = conn
+            .query_row("SELECT strftime('%Y', 'now')", [], |row| row.get(0))
+            .unwrap();
+        let mut recent = Item {
+            id: "e".to_string(),
+            name: "E".to_string(),
+            path: "/music/e.flac".to_string(),
+            ..Default::default()
+        };
+        recent.album.id = "album-recent".to_string();
+        recent.album.release_date = year.clone();
+        save_track(&conn, &recent).unwrap();
+        let released = rules(json!({
+            "rules": [{ "field": "release_date", "op": "in_last", "value": 30 }],
+        }));
+        assert_eq!(smart_track_ids(&conn, &released, &music).unwrap(), ["e"]);
+        let before = rules(json!({
+            "rules": [{ "field": "release_date", "op": "before", "value": "2001-03" }],
+        }));
+        assert_eq!(smart_track_ids(&conn, &before, &music).unwrap(), ["c"]);
+        let older = rules(json!({
+            "rules": [{ "field": "release_date", "op": "not_in_last", "value": 30 }],
+        }));
+        assert!(!smart_track_ids(&conn, &older, &music)
+            .unwrap()
+            .contains(&"e".to_string()));
+
+        assert!(smart_track_ids(&conn, &after, &["/other/".to_string()])
+            .unwrap()
+            .is_empty());
+
+        let invalid: SmartRules = serde_json::from_value(json!({
+            "rules": [{ "field": "added", "op": "before", "value": "last week" }],
+        }))
+        .unwrap();
+        assert!(check_rules(&invalid).is_err());
+        assert!(serde_json::from_value::<SmartRules>(json!({
+            "rules": [{ "field": "popularity", "op": "contains", "value": 5 }],
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+
+use super::library::within_sql;
+use crate::data::matcher::levenshtein;
+use crate::data::search::{SearchField, SearchKind, SearchToken};
+
+// Contenu indexé pour chaque type d'élément : nom, artistes, album et année
+const TRACK_DOCUMENT: &str = "
+    SELECT tracks.name,
+        trim(tracks.artist || ' ' || COALESCE((SELECT group_concat(artists.name, ' ')
+            FROM track_artists JOIN artists ON artists.id = track_artists.artist_id
+            WHERE track_artists.track_id = tracks.id), '')),
+        albums.name, substr(albums.release_date, 1, 4)
+    FROM tracks JOIN albums ON albums.id = tracks.album_id WHERE tracks.id = ?1";
+
+const ALBUM_DOCUMENT: &str = "
+    SELECT albums.name,
+        trim(albums.artist || ' ' || COALESCE((SELECT group_concat(artists.name, ' ')
+            FROM album_artists JOIN artists ON artists.id = album_artists.artist_id
+            WHERE album_artists.album_id = albums.id), '')),
+        albums.name, substr(albums.release_date, 1, 4)
+    FROM albums WHERE albums.id = ?1";
+
+const ARTIST_DOCUMENT: &str =
+    "SELECT artists.name, artists.name, '', '' FROM artists WHERE artists.id = ?1";
+
+// Le nom compte plus que les artistes, puis l'album et l'année
+const RANKING: &str = "bm25(search_index, 10.0, 5.0, 3.0, 1.0)";
+
+// Corrections proposées au plus pour un terme inconnu
+const MAX_CORRECTIONS: usize = 10;
+
+pub fn index_track(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    index_item(conn, "track", id, TRACK_DOCUMENT)
+}
+
+pub fn index_album(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    index_item(conn, "album", id, ALBUM_DOCUMENT)
+}
+
+pub fn index_artist(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    index_item(conn, "artist", id, ARTIST_DOCUMENT)
+}
+
+// Les suppressions sont faites par les déclencheurs des tables tracks, albums et artists
+fn index_item(conn: &Connection, kind: &str, id: &str, document: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO search_items (kind, item_id) VALUES (?1, ?2)",
+        params![kind, id],
+    )?;
+    let rowid: i64 = conn.query_row(
+        "SELECT id FROM search_items WHERE kind = ?1 AND item_id = ?2",
+        params![kind, id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute("DELETE FROM search_index WHERE rowid = ?1", params![rowid])?;
+    conn.execute(
+        &format!(
+            "INSERT INTO search_index (rowid, name, artist, album, year)
+             SELECT ?2, document.* FROM ({}) AS document",
+            document
+        ),
+        params![id, rowid],
+    )?;
+
+    Ok(())
+}
+
+// Expression FTS5 : chaque terme est cherché en préfixe, ou corrigé s'il n'existe pas dans l'index
+pub fn match_expression(conn: &Connection, tokens: &[SearchToken]) -> rusqlite::Result<String> {
+    let mut parts = Vec::new();
+
+    for token in tokens {
+        let expression = if token.phrase {
+            format!("\"{}\"", token.terms.join(" "))
+        } else if token.field == Some(SearchField::Year) {
+            quoted(&token.terms).join(" AND ")
+        } else {
+            let mut terms = Vec::new();
+            for term in &token.terms {
+                terms.push(term_expression(conn, term)?);
+            }
+            terms.join(" AND ")
+        };
+
+        let columns = token
+            .field
+            .map(|field| field.column())
+            .unwrap_or("{name artist album}");
+        parts.push(format!("{} : ({})", columns, expression));
+    }
+
+    Ok(parts.join(" AND "))
+}
+
+fn term_expression(conn: &Connection, term: &str) -> rusqlite::Result<String> {
+    let prefix = format!("\"{}\"*", term);
+    let known: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM search_terms WHERE term >= ?1 AND term < ?1 || char(1114111))",
+        params![term],
+        |row| row.get(0),
+    )?;
+
+    let max_typos = match term.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    if known || max_typos == 0 {
+        return Ok(prefix);
+    }
+
+    let corrections = corrections(conn, term, max_typos)?;
+    if corrections.is_empty() {
+        return Ok(prefix);
+    }
+
+    Ok(format!(
+        "{} OR {}",
+        prefix,
+        quoted(&corrections).join(" OR ")
+    ))
+}
+
+// Termes de l'index proches du terme cherché, entier ou en début de mot
+fn corrections(conn: &Connection, term: &str, max_typos: usize) -> rusqlite::Result<Vec<String>> {
+    let term: Vec<char> = term.chars().collect();
+    let mut stmt = conn.prepare("SELECT term, doc FROM search_terms WHERE length(term) >= ?1")?;
+    let rows = stmt.query_map(params![(term.len() - max_typos) as i64], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+
+    let mut candidates = Vec::new();
+    for row in rows {
+        let (candidate, documents) = row?;
+        let chars: Vec<char> = candidate.chars().collect();
+        let mut distance = levenshtein(&term, &chars);
+        if chars.len() > term.len() {
+            distance = distance.min(levenshtein(&term, &chars[..term.len()]));
+        }
+        if distance <= max_typos {
+            candidates.push((distance, -documents, candidate));
+        }
+    }
+    candidates.sort();
+
+    Ok(candidates
+        .into_iter()
+        .take(MAX_CORRECTIONS)
+        .map(|(_, _, candidate)| candidate)
+        .collect())
+}
+
+fn quoted(terms: &[String]) -> Vec<String> {
+    terms.iter().map(|term| format!("\"{}\"", term)).collect()
+}
+
+// Identifiants d'une page de résultats ayant un titre sous un des préfixes, du plus au moins
+// pertinent, et nombre total
+pub fn search_ids(
+    conn: &Connection,
+    expression: &str,
+    kind: SearchKind,
+    prefixes: &[String],
+    limit: usize,
+    offset: usize,
+) -> rusqlite::Result<(Vec<String>, usize)> {
+    let mut params = vec![
+        Value::Text(expression.to_string()),
+        Value::Text(kind.item_kind().to_string()),
+    ];
+    let condition = format!(
+        "search_index MATCH ?1 AND search_items.kind = ?2
+         AND EXISTS (SELECT 1 FROM tracks WHERE {} AND {})",
+        visible_sql(kind),
+        within_sql(prefixes, &mut params)
+    );
+    let total: i64 = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM search_index
+             JOIN search_items ON search_items.id = search_index.rowid
+             WHERE {}",
+            condition
+        ),
+        params_from_iter(&params),
+        |row| row.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT search_items.item_id FROM search_index
+         JOIN search_items ON search_items.id = search_index.rowid
+         WHERE {} ORDER BY {}, search_items.item_id LIMIT ? OFFSET ?",
+        condition, RANKING
+    ))?;
+    params.push(Value::Integer(limit as i64));
+    params.push(Value::Integer(offset as i64));
+    let rows = stmt.query_map(params_from_iter(params), |row| row.get(0))?;
+
+    Ok((rows.collect::<rusqlite::Result<_>>()?, total as usize))
+}
+
+// Titres qui rendent un élément visible : le titre lui-même, ceux de l'album ou de l'artiste
+fn visible_sql(kind: SearchKind) -> &'static str {
+    match kind {
+        SearchKind::Tracks => "tracks.id = search_items.item_id",
+        SearchKind::Albums => "tracks.album_id = search_items.item_id",
+        SearchKind::Artists => {
+            "(tracks.id IN (SELECT track_id FROM track_artists
+                            WHERE artist_id = search_items.item_id)
+              OR tracks.album_id IN (SELECT album_id FROM album_artists
+                                     WHERE artist_id = search_items.item_id))"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::models::{Album, Artist, Item};
+    use crate::data::search::parse_query;
+    use crate::database::database::Database;
+    use crate::database::library::{prune_orphans, remove_track, save_track};
+
+    fn ids(conn: &Connection, query: &str, kind: SearchKind) -> Vec<String> {
+        let expression = match_expression(conn, &parse_query(query)).unwrap();
+        search_ids(conn, &expression, kind, &["/music/".to_string()], 10, 0)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn search_uses_prefixes_typos_and_filters() {
+        let db = Database::open(":memory:").unwrap();
+        let conn = db.connection();
+
+        let tracks = [
+            (
+                "one-more-time",
+                "One More Time",
+                "Daft Punk",
+                "Discovery",
+                "2001-03-12",
+            ),
+            (
+                "harder",
+                "Harder, Better, Faster, Stronger",
+                "Daft Punk",
+                "Discovery",
+                "2001-03-12",
+            ),
+            (
+                "get-lucky",
+                "Get Lucky",
+                "Daft Punk",
+                "Random Access Memories",
+                "2013-05-17",
+            ),
+            ("sexy-boy", "Sexy Boy", "Air", "Moon Safari", "1998"),
+        ];
+        for (id, name, artist, album, release_date) in tracks {
+            let track = Item {
+                id: id.to_string(),
+                name: name.to_string(),
+                path: format!("/music/{}.mp3", id),
+                artists: vec![Artist {
+                    id: artist.to_lowercase(),
+                    name: artist.to_string(),
+                    ..Default::default()
+                }],
+                album: Album {
+                    id: album.to_lowercase(),
+                    name: album.to_string(),
+                    release_date: release_date.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            save_track(&conn, &track).unwrap();
+        }
+
+        assert_eq!(ids(&conn, "harde", SearchKind::Tracks), ["harder"]);
+        assert_eq!(ids(&conn, "luckky", SearchKind::Tracks), ["get-lucky"]);
+        assert_eq!(ids(&conn, "dafd", SearchKind::Artists), ["daft punk"]);
+        assert_eq!(
+            ids(&conn, "artist:daft year:2001 one", SearchKind::Tracks),
+            ["one-more-time"]
+        );
+        assert_eq!(
+            ids(&conn, "album:\"access memories\"", SearchKind::Albums),
+            ["random access memories"]
+        );
+
+        let expression = match_expression(&conn, &parse_query("daft")).unwrap();
+        let music = ["/music/".to_string()];
+        let (page, total) =
+            search_ids(&conn, &expression, SearchKind::Tracks, &music, 2, 2).unwrap();
+        assert_eq!((page.len(), total), (1, 3));
+
+        // Hors des dossiers consultables, rien n'est trouvé
+        let other = ["/other/".to_string()];
+        for kind in [SearchKind::Tracks, SearchKind::Albums, SearchKind::Artists] {
+            let (page, total) = search_ids(&conn, &expression, kind, &other, 10, 0).unwrap();
+            assert_eq!((page.len(), total), (0, 0));
+        }
+
+        // Les éléments supprimés disparaissent de l'index
+        remove_track(&conn, "/music/sexy-boy.mp3").unwrap();
+        prune_orphans(&conn).unwrap();
+        assert!(ids(&conn, "air", SearchKind::Artists).is_empty());
+        assert!(ids(&conn, "safari", SearchKind::Albums).is_empty());
+        assert!(ids(&conn, "sexy", SearchKind::Tracks).is_empty());
+    }
+}
@@ -1,46 +1,166 @@
 mod api {
+    #[cfg(test)]
+    pub mod mock;
     pub mod spotify;
 }
 
 mod data {
+    pub mod covers;
+    pub mod jobs;
+    pub mod libraries;
+    pub mod matcher;
     pub mod models;
+    pub mod playlist_files;
+    pub mod review;
+    pub mod search;
+    pub mod tags;
+    pub mod transcode;
     pub mod utils;
+    pub mod watcher;
+}
+
+mod database {
+    pub mod covers;
+    #[allow(clippy::module_inception)]
+    pub mod database;
+    pub mod files;
+    pub mod library;
+    pub mod matches;
+    pub mod playlists;
+    pub mod rules;
+    pub mod search;
 }
 
 mod controllers {
+    pub mod covers;
+    pub mod errors;
     pub mod home;
+    pub mod library;
+    pub mod playlists;
+    pub mod review;
+    pub mod search;
     pub mod tracks;
 }
 
+mod cli {
+    pub mod commands;
+}
+
 mod settings {
-    pub mod env;
+    #[allow(clippy::module_inception)]
+    pub mod settings;
 }
 
-use api::spotify::{spotify_get, spotify_search};
+use api::spotify::{spotify_get, spotify_search, SpotifyClient};
+use cli::commands::{execute, Cli, Command};
 use controllers::{
+    covers::{get_album_cover, get_album_image},
     home::get_home,
-    tracks::{get_albums, get_artists, get_tracks},
+    library::{cancel_scan, get_libraries, get_scan, get_scans, start_scan},
+    playlists::{
+        add_playlist_tracks, edit_playlist, export_playlist, get_playlist, get_playlists,
+        move_playlist_track, new_playlist, remove_playlist, remove_playlist_track,
+    },
+    review::{accept_candidate, get_review_queue, keep_local, match_track},
+    search::search_library,
+    tracks::{get_album, get_albums, get_artist, get_artists, get_track, get_tracks, stream_track},
 };
+use data::jobs::ScanJobs;
+use data::transcode::Transcoder;
+use data::watcher::watch;
+use database::database::Database;
+use settings::settings::Settings;
 
 use actix_cors::Cors;
 use actix_web::{http, web, App, HttpServer};
+use clap::Parser;
+use dotenv::dotenv;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
-            .allowed_methods(vec!["GET"])
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    // Réglages invalides : tout est signalé avant de démarrer
+    let settings = Settings::load().map_err(|errors| {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        std::io::Error::other("Invalid settings")
+    })?;
+
+    match cli.command {
+        None | Some(Command::Serve) => serve(settings).await,
+        Some(command) => {
+            let (mut out, mut err) = (std::io::stdout(), std::io::stderr());
+            let status = execute(command, cli.json, &settings, &mut out, &mut err).await;
+            if status != 0 {
+                std::process::exit(status);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn serve(settings: Settings) -> std::io::Result<()> {
+    let database = Database::open(&settings.database.path).map_err(std::io::Error::other)?;
+    let spotify = SpotifyClient::new(settings.spotify.clone());
+    let scan_jobs = ScanJobs::default();
+    let transcoder = Transcoder::new(settings.transcode.clone());
+    let libraries = settings.libraries.clone();
+    let cors_origins = settings.server.cors_origins.clone();
+
+    // Mode surveillance : les dossiers configurés sont réindexés au fil des changements
+    let _watcher = if settings.watch.enabled {
+        Some(
+            watch(
+                &settings.watch,
+                libraries.clone(),
+                database.clone(),
+                spotify.clone(),
+                scan_jobs.clone(),
+            )
+            .map_err(std::io::Error::other)?,
+        )
+    } else {
+        None
+    };
+
+    HttpServer::new(move || {
+        let cors = cors_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
+            .allowed_header(http::header::RANGE)
+            .expose_headers(vec![
+                http::header::ACCEPT_RANGES,
+                http::header::CONTENT_RANGE,
+                http::header::ETAG,
+            ])
             .max_age(3600);
 
         App::new()
             .wrap(cors)
+            .app_data(web::Data::new(database.clone()))
+            .app_data(web::Data::new(spotify.clone()))
+            .app_data(web::Data::new(scan_jobs.clone()))
+            .app_data(web::Data::new(transcoder.clone()))
+            .app_data(web::Data::new(libraries.clone()))
             .configure(spotify_routes) // Spotify Routes
+            .configure(libraries_routes) // Libraries Routes
+            .configure(library_routes) // Library Routes
+            .configure(playlist_routes) // Playlist Routes
+            .service(stream_track)
+            .service(get_track)
+            .service(get_album)
+            .service(get_artist)
+            .service(get_album_cover)
+            .service(get_album_image)
             .service(get_home)
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind(settings.server.bind)?
     .run()
     .await
 }
@@ -48,10 +168,47 @@ async fn main() -> std::io::Result<()> {
 fn spotify_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/spotify")
-            .service(get_tracks)
-            .service(get_albums)
-            .service(get_artists)
             .service(spotify_get)
             .service(spotify_search),
     );
 }
+
+fn libraries_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/libraries")
+            .service(get_libraries)
+            .service(get_tracks)
+            .service(get_albums)
+            .service(get_artists),
+    );
+}
+
+fn library_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/library")
+            .service(start_scan)
+            .service(get_scans)
+            .service(get_scan)
+            .service(cancel_scan)
+            .service(get_review_queue)
+            .service(accept_candidate)
+            .service(match_track)
+            .service(keep_local)
+            .service(search_library),
+    );
+}
+
+fn playlist_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/playlists")
+            .service(get_playlists)
+            .service(new_playlist)
+            .service(get_playlist)
+            .service(export_playlist)
+            .service(edit_playlist)
+            .service(remove_playlist)
+            .service(add_playlist_tracks)
+            .service(move_playlist_track)
+            .service(remove_playlist_track),
+    );
+}
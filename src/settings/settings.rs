@@ -0,0 +1,334 @@
+use actix_web::http::Uri;
+use serde::Deserialize;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::api::spotify::SpotifyConfig;
+use crate::data::libraries::Libraries;
+use crate::data::transcode::TranscodeConfig;
+use crate::data::watcher::WatchConfig;
+use crate::database::database::DatabaseConfig;
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+const DEFAULT_CORS_ORIGIN: &str = "http://localhost:3000";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: DEFAULT_BIND.parse().unwrap(),
+            cors_origins: vec![DEFAULT_CORS_ORIGIN.to_string()],
+        }
+    }
+}
+
+// Chaque section a ses valeurs par défaut : un fichier vide suffit
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub spotify: SpotifyConfig,
+    pub transcode: TranscodeConfig,
+    pub watch: WatchConfig,
+    pub libraries: Libraries,
+}
+
+impl Settings {
+    // Fichier TOML puis variables d'environnement, qui l'emportent ; toutes les erreurs sont renvoyées ensemble
+    pub fn load() -> Result<Settings, Vec<String>> {
+        let (path, required) = match env::var("CONFIG_PATH") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_string(), false),
+        };
+
+        let mut settings = match fs::read_to_string(&path) {
+            Ok(content) => {
+                Settings::parse(&content).map_err(|err| vec![format!("{}: {}", path, err)])?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                eprintln!("{}: not found, using defaults", path);
+                Settings::default()
+            }
+            Err(err) => return Err(vec![format!("{}: {}", path, err)]),
+        };
+
+        let mut errors = settings.apply_env(|name| env::var(name).ok());
+        errors.extend(settings.validate());
+
+        if errors.is_empty() {
+            Ok(settings)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Settings, String> {
+        toml::from_str(content).map_err(|err| err.to_string())
+    }
+
+    // Les bibliothèques ne se déclarent que dans le fichier
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut set = |name: &str, target: &mut dyn FnMut(&str) -> Result<(), String>| {
+            if let Some(value) = var(name) {
+                if let Err(err) = target(&value) {
+                    errors.push(format!("{}: {}", name, err));
+                }
+            }
+        };
+
+        set("BIND_ADDRESS", &mut parse_into(&mut self.server.bind));
+        set("CORS_ORIGINS", &mut |value| {
+            self.server.cors_origins = value
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+            Ok(())
+        });
+        set("DATABASE_PATH", &mut parse_into(&mut self.database.path));
+        set(
+            "SPOTIFY_CLIENT_ID",
+            &mut parse_into(&mut self.spotify.client_id),
+        );
+        set(
+            "SPOTIFY_CLIENT_SECRET",
+            &mut parse_into(&mut self.spotify.client_secret),
+        );
+        set(
+            "SPOTIFY_API_URL",
+            &mut parse_into(&mut self.spotify.api_url),
+        );
+        set(
+            "SPOTIFY_TOKEN_URL",
+            &mut parse_into(&mut self.spotify.token_url),
+        );
+        set(
+            "SPOTIFY_REQUESTS_PER_SECOND",
+            &mut parse_into(&mut self.spotify.requests_per_second),
+        );
+        set("FFMPEG_PATH", &mut parse_into(&mut self.transcode.encoder));
+        set(
+            "TRANSCODE_CACHE_DIR",
+            &mut parse_into(&mut self.transcode.cache_dir),
+        );
+        set(
+            "TRANSCODE_MAX_JOBS",
+            &mut parse_into(&mut self.transcode.max_jobs),
+        );
+        set("LIBRARY_WATCH", &mut parse_into(&mut self.watch.enabled));
+        set(
+            "WATCH_DEBOUNCE_MS",
+            &mut parse_into(&mut self.watch.debounce_ms),
+        );
+
+        errors
+    }
+
+    fn validate(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.server.cors_origins.is_empty() {
+            errors.push("server.cors_origins: at least one origin is required".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if !is_origin(origin) {
+                errors.push(format!(
+                    "server.cors_origins: {:?} is not an origin such as http://localhost:3000",
+                    origin
+                ));
+            }
+        }
+
+        if self.database.path.is_empty() {
+            errors.push("database.path: must not be empty".to_string());
+        }
+
+        for (name, url) in [
+            ("spotify.api_url", &self.spotify.api_url),
+            ("spotify.token_url", &self.spotify.token_url),
+        ] {
+            if !is_http_url(url) {
+                errors.push(format!("{}: {:?} is not an http(s) URL", name, url));
+            }
+        }
+        if !self.spotify.api_url.ends_with('/') {
+            self.spotify.api_url.push('/');
+        }
+        if !(self.spotify.requests_per_second.is_finite() && self.spotify.requests_per_second > 0.0)
+        {
+            errors.push("spotify.requests_per_second: must be a positive number".to_string());
+        }
+        match (
+            self.spotify.client_id.is_empty(),
+            self.spotify.client_secret.is_empty(),
+        ) {
+            (true, true) => eprintln!("Spotify credentials missing: tracks will not be matched"),
+            (false, false) => {}
+            _ => errors.push("spotify: client_id and client_secret go together".to_string()),
+        }
+
+        if self.transcode.encoder.is_empty() {
+            errors.push("transcode.encoder: must not be empty".to_string());
+        }
+        if self.transcode.max_jobs == 0 {
+            errors.push("transcode.max_jobs: must be at least 1".to_string());
+        }
+
+        for library in self.libraries.enabled() {
+            if let Err(err) = library.path.canonicalize() {
+                errors.push(format!(
+                    "libraries.{}.path: {}: {}",
+                    library.id,
+                    library.path.display(),
+                    err
+                ));
+            }
+        }
+
+        if self.watch.enabled && self.libraries.enabled().next().is_none() {
+            eprintln!("Watching is enabled but no library is configured");
+        }
+
+        errors
+    }
+}
+
+fn parse_into<T>(target: &mut T) -> impl FnMut(&str) -> Result<(), String> + '_
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    |value| {
+        *target = value.parse().map_err(|err: T::Err| err.to_string())?;
+        Ok(())
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.parse::<Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http") | Some("https")) && uri.host().is_some()
+    })
+}
+
+// Schéma, hôte et port seulement, comme l'en-tête Origin envoyé par le navigateur
+fn is_origin(origin: &str) -> bool {
+    is_http_url(origin)
+        && origin.parse::<Uri>().is_ok_and(|uri| {
+            uri.scheme_str()
+                .zip(uri.authority())
+                .is_some_and(|(scheme, authority)| origin == format!("{}://{}", scheme, authority))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn file_values_are_overridden_by_the_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().canonicalize().unwrap();
+        let mut settings = Settings::parse(&format!(
+            r#"
+            [server]
+            bind = "0.0.0.0:9000"
+            cors_origins = ["https://music.example.com"]
+
+            [spotify]
+            client_id = "file-id"
+            client_secret = "file-secret"
+            api_url = "http://localhost:4000/v1"
+
+            [[libraries]]
+            id = "music"
+            name = "Music"
+            path = "{}"
+            exclude = ["Podcasts/**"]
+            "#,
+            music.display()
+        ))
+        .unwrap();
+        let env = HashMap::from([
+            (
+                "CORS_ORIGINS",
+                "http://localhost:3000, http://localhost:5173",
+            ),
+            ("SPOTIFY_CLIENT_SECRET", "env-secret"),
+            ("LIBRARY_WATCH", "true"),
+        ]);
+
+        assert!(settings
+            .apply_env(|name| env.get(name).map(|value| value.to_string()))
+            .is_empty());
+        assert!(settings.validate().is_empty());
+
+        assert_eq!(settings.server.bind.port(), 9000);
+        assert_eq!(
+            settings.server.cors_origins,
+            vec!["http://localhost:3000", "http://localhost:5173"]
+        );
+        assert_eq!(
+            (
+                settings.spotify.client_id.as_str(),
+                settings.spotify.client_secret.as_str()
+            ),
+            ("file-id", "env-secret")
+        );
+        assert_eq!(settings.spotify.api_url, "http://localhost:4000/v1/");
+        assert_eq!(settings.database.path, "library.db");
+        assert!(settings.watch.enabled);
+        assert!(settings.libraries.get("music").is_some_and(|music| {
+            music.contains(&dir.path().join("01.flac"))
+                && !music.contains(&dir.path().join("Podcasts/01.mp3"))
+        }));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let mut settings = Settings::parse(
+            r#"
+            [server]
+            cors_origins = ["localhost:3000", "http://localhost:3000/app"]
+
+            [spotify]
+            client_id = "only-id"
+            requests_per_second = 0
+
+            [[libraries]]
+            id = "missing"
+            name = "Missing"
+            path = "/nonexistent/music"
+            "#,
+        )
+        .unwrap();
+        let env = HashMap::from([("BIND_ADDRESS", "localhost"), ("WATCH_DEBOUNCE_MS", "soon")]);
+
+        let mut errors = settings.apply_env(|name| env.get(name).map(|value| value.to_string()));
+        errors.extend(settings.validate());
+        assert_eq!(errors.len(), 7, "{:#?}", errors);
+        assert!(errors[0].starts_with("BIND_ADDRESS: "));
+
+        for content in [
+            "[server]\nport = 8080",
+            "[[libraries]]\nid = \"\"\nname = \"Music\"\npath = \"/music\"",
+            "[[libraries]]\nid = \"music\"\nname = \"Music\"\npath = \"/music\"\ninclude = [\"[\"]",
+        ] {
+            assert!(Settings::parse(content).is_err(), "{}", content);
+        }
+    }
+}